@@ -0,0 +1,116 @@
+use std::hint::black_box;
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+use par_calc::{auto_chunk_lines, benchmark_all, compress_counter, count_chars, count_chars_parallel, count_chunks_queued, merge_compressed, merge_counts, CharCounter, Split};
+
+const LINES: usize = 10_000;
+
+// Deterministic mix of short and long lines with some non-ASCII characters
+fn synthetic_input() -> Vec<String> {
+    let words = ["the", "whale", "Ahab", "sea", "Pequod", "harpoon", "naïve", "café", "日本", "—"];
+    (0..LINES)
+        .map(|i| {
+            let len = 3 + (i * 7919) % 17;
+            (0..len).map(|j| words[(i + j * 31) % words.len()]).collect::<Vec<_>>().join(" ")
+        })
+        .collect()
+}
+
+fn bench_counting(c: &mut Criterion) {
+    let input = synthetic_input();
+    let bytes: usize = input.iter().map(String::len).sum();
+
+    let mut group = c.benchmark_group("counting");
+    group.throughput(Throughput::Bytes(bytes as u64));
+    group.bench_function("count_chars", |b| b.iter(|| count_chars(black_box(&input))));
+    for threads in [1, 2, 4, 8] {
+        group.bench_with_input(BenchmarkId::new("count_chars_parallel", threads), &threads, |b, &threads| {
+            b.iter(|| count_chars_parallel(black_box(&input), threads).unwrap())
+        });
+    }
+    #[cfg(feature = "dashmap")]
+    for threads in [1, 2, 4, 8] {
+        group.bench_with_input(BenchmarkId::new("count_chars_dashmap", threads), &threads, |b, &threads| {
+            b.iter(|| par_calc::count_chars_dashmap(black_box(&input), threads))
+        });
+    }
+    // One rerun of the sweep over 1 to 8 threads reads the input 8 times
+    group.throughput(Throughput::Bytes(bytes as u64 * 8));
+    group.bench_function("benchmark_all", |b| b.iter(|| benchmark_all(black_box(&input), &[1, 2, 3, 4, 5, 6, 7, 8], 1, Split::default()).unwrap()));
+    group.finish();
+}
+
+// Per-thread counts of DNA, a four letter alphabet with the odd N for an unknown base
+fn dna_parts(parts: usize) -> Vec<CharCounter> {
+    let bases = ['A', 'C', 'G', 'T', 'A', 'T', 'N'];
+    (0..parts)
+        .map(|part| {
+            let line: String = (0..1000).map(|i| bases[(part * 7 + i * i * 31) % bases.len()]).collect();
+            count_chars(&[line])
+        })
+        .collect()
+}
+
+// Only the merging of the per-thread results, the part of the count the receiving end does
+fn bench_merge(c: &mut Criterion) {
+    let mut group = c.benchmark_group("merge");
+    for parts in [8, 64, 512] {
+        let maps = dna_parts(parts);
+        let compressed: Vec<_> = maps.iter().map(compress_counter).collect();
+        group.bench_with_input(BenchmarkId::new("merge_counts", parts), &maps, |b, maps| {
+            b.iter(|| {
+                let mut counter = CharCounter::default();
+                for part in black_box(maps) {
+                    merge_counts(&mut counter, part);
+                }
+                counter
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("merge_compressed", parts), &compressed, |b, compressed| {
+            b.iter_batched(|| compressed.clone(), |parts| parts.into_iter().reduce(merge_compressed), BatchSize::SmallInput)
+        });
+    }
+    group.finish();
+}
+
+// The first eighth of the lines are 50 times longer, so one thread per chunk leaves the first one
+// with about 7 times the work of the others
+fn skewed_input() -> Vec<String> {
+    (0..100_000).map(|i| if i < 12_500 { "skewed line ".repeat(50) } else { "skewed line ".to_string() }).collect()
+}
+
+fn bench_skewed(c: &mut Criterion) {
+    let input = skewed_input();
+    let mut group = c.benchmark_group("skewed");
+    group.throughput(Throughput::Bytes(input.iter().map(String::len).sum::<usize>() as u64));
+    for threads in [1, 4, 8] {
+        group.bench_with_input(BenchmarkId::new("per_thread", threads), &threads, |b, &threads| {
+            b.iter(|| count_chars_parallel(black_box(&input), threads).unwrap())
+        });
+        let chunk_lines = auto_chunk_lines(input.len(), threads);
+        group.bench_with_input(BenchmarkId::new("queued", threads), &threads, |b, &threads| {
+            b.iter(|| count_chunks_queued(black_box(&input), threads, chunk_lines).unwrap())
+        });
+    }
+    group.finish();
+}
+
+// Reading the file is part of what is timed, count_chars_par_bridge never has all of it in memory
+#[cfg(feature = "rayon")]
+fn bench_from_file(c: &mut Criterion) {
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/2701-0.txt");
+    let mut group = c.benchmark_group("from_file");
+    group.throughput(Throughput::Bytes(std::fs::metadata(path).unwrap().len()));
+    group.bench_function("count_chars_par_bridge", |b| b.iter(|| par_calc::count_chars_par_bridge(black_box(path)).unwrap()));
+    for threads in [1, 4, 8] {
+        group.bench_with_input(BenchmarkId::new("load_file+count_chars_parallel", threads), &threads, |b, &threads| {
+            b.iter(|| count_chars_parallel(&par_calc::load_file(black_box(path), None).unwrap(), threads).unwrap())
+        });
+    }
+    group.finish();
+}
+
+#[cfg(not(feature = "rayon"))]
+fn bench_from_file(_: &mut Criterion) {}
+
+criterion_group!(benches, bench_counting, bench_merge, bench_skewed, bench_from_file);
+criterion_main!(benches);
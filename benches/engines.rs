@@ -0,0 +1,45 @@
+use std::hint::black_box;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use par_calc::{CountEngine, Engine};
+
+// Each corpus is repeated to about this many bytes so that the threads have something to share
+const BYTES: usize = 1 << 20;
+
+const PROSE: &str = include_str!("corpora/prose.txt");
+const CJK: &str = include_str!("corpora/cjk.txt");
+
+fn repeated(text: &str) -> String {
+    text.repeat(BYTES.div_ceil(text.len()))
+}
+
+// ASCII prose, CJK text and the prose as one giant line that can't be split between threads
+fn corpora() -> Vec<(&'static str, Vec<String>)> {
+    let lines = |text: String| text.lines().map(str::to_string).collect();
+    vec![
+        ("ascii_prose", lines(repeated(PROSE))),
+        ("cjk", lines(repeated(CJK))),
+        ("one_giant_line", vec![repeated(PROSE).replace('\n', " ")]),
+    ]
+}
+
+fn bench_engines(c: &mut Criterion) {
+    for (name, input) in corpora() {
+        let input: Vec<&str> = input.iter().map(String::as_str).collect();
+        let bytes: usize = input.iter().map(|line| line.len()).sum();
+        let mut group = c.benchmark_group(name);
+        group.throughput(Throughput::Bytes(bytes as u64));
+        for engine in Engine::available() {
+            // The serial engine ignores the thread count
+            let threads: &[usize] = if engine == Engine::Serial { &[1] } else { &[1, 4, 8] };
+            for &threads in threads {
+                group.bench_with_input(BenchmarkId::new(engine.name(), threads), &threads, |b, &threads| {
+                    b.iter(|| engine.count(black_box(&input), threads).unwrap())
+                });
+            }
+        }
+        group.finish();
+    }
+}
+
+criterion_group!(benches, bench_engines);
+criterion_main!(benches);
@@ -0,0 +1,17 @@
+// Checks that the parallel counter always agrees with the single-threaded one.
+//
+// Run with `cargo +nightly fuzz run fuzz_count_chars` from the repository root
+// (requires `cargo install cargo-fuzz`).
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use par_calc::{count_chars, count_chars_parallel};
+
+fuzz_target!(|input: (Vec<String>, u8)| {
+    let (lines, threads) = input;
+    let threads = 1 + threads as usize % 16;
+    let expected = count_chars(&lines);
+    assert_eq!(expected, count_chars_parallel(&lines, threads).unwrap());
+    let total: u64 = lines.iter().map(|line| line.chars().count() as u64).sum();
+    assert_eq!(total, expected.values().sum::<u64>());
+});
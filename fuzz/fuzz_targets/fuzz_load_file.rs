@@ -0,0 +1,20 @@
+// Feeds arbitrary bytes to `load_file` as file content. Invalid UTF-8 must come back as an error,
+// never a panic, and valid input must round-trip to the same lines as `str::lines`.
+//
+// Run with `cargo +nightly fuzz run fuzz_load_file` from the repository root
+// (requires `cargo install cargo-fuzz`).
+#![no_main]
+
+use std::io::Write;
+use libfuzzer_sys::fuzz_target;
+use par_calc::load_file;
+
+fuzz_target!(|data: &[u8]| {
+    let path = std::env::temp_dir().join(format!("par_calc_fuzz_{}", std::process::id()));
+    std::fs::File::create(&path).unwrap().write_all(data).unwrap();
+    let loaded = load_file(path.to_str().unwrap(), None);
+    match std::str::from_utf8(data) {
+        Ok(text) => assert_eq!(loaded.unwrap(), text.lines().collect::<Vec<_>>()),
+        Err(_) => assert!(loaded.is_err()),
+    }
+});
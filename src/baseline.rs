@@ -0,0 +1,67 @@
+// --baseline: compares the sweep with one saved earlier with --bench-json, a simple performance gate for CI
+use std::fs;
+use serde::Deserialize;
+use par_calc::{BenchResult, RunReport, TimeUnit};
+use crate::cli::Options;
+use crate::error::Error;
+use crate::{fmt_time, invalid_data};
+
+// Only what the comparison needs of the --bench-json document, or a --format json report of a bench
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Baseline {
+    BenchJson { results: Vec<BaselineRun> },
+    Report(Box<RunReport>),
+}
+
+#[derive(Deserialize)]
+struct BaselineRun {
+    threads: usize,
+    mean_ns: f64,
+}
+
+fn load(path: &str) -> Result<Vec<BaselineRun>, Error> {
+    let text = fs::read_to_string(path).map_err(|e| Error::open(path, e))?;
+    match serde_json::from_str(&text).map_err(|e| invalid_data(format!("{path}: {e}")))? {
+        Baseline::BenchJson { results } => Ok(results),
+        Baseline::Report(report) => match report.bench {
+            Some(results) => Ok(results.iter().map(|r| BaselineRun { threads: r.threads, mean_ns: r.mean_nanos() }).collect()),
+            None => Err(invalid_data(format!("{path}: the report has no benchmark results"))),
+        },
+    }
+}
+
+// Fails naming the thread counts more than --regression-threshold percent slower than the baseline,
+// after printing the change of every one
+pub fn check(results: &[BenchResult], path: &str, opts: &Options) -> Result<(), Error> {
+    let baseline = load(path)?;
+    let threshold = opts.regression_threshold;
+    let fastest = results.iter().map(BenchResult::mean_nanos).reduce(f64::min);
+    let unit = opts.time_unit.unwrap_or_else(|| TimeUnit::fitting(fastest.unwrap_or(0.0) as u64));
+    opts.verbosity.info(&format!("Compared with the baseline {path}:"));
+    let mut regressed = Vec::new();
+    for result in results {
+        let Some(old) = baseline.iter().find(|run| run.threads == result.threads) else {
+            opts.verbosity.info(&format!(" - {} threads: not in the baseline", result.threads));
+            continue;
+        };
+        let new = result.mean_nanos();
+        let change = (new - old.mean_ns) * 100.0 / old.mean_ns;
+        let line = format!(
+            " - {} threads: {} -> {} ({change:+.2}%)",
+            result.threads, fmt_time(old.mean_ns, unit, opts.group), fmt_time(new, unit, opts.group)
+        );
+        if change > threshold {
+            opts.verbosity.info(&opts.style.red(&format!("{line}, regression")));
+            regressed.push(result.threads);
+        } else if change < -threshold {
+            opts.verbosity.info(&opts.style.green(&line));
+        } else {
+            opts.verbosity.info(&line);
+        }
+    }
+    if regressed.is_empty() {
+        return Ok(());
+    }
+    Err(Error::Regression(regressed, threshold))
+}
@@ -0,0 +1,545 @@
+use std::ffi::OsString;
+use std::num::ParseIntError;
+use std::str::FromStr;
+use std::thread;
+use std::time::Duration;
+use clap::builder::{PossibleValuesParser, RangedU64ValueParser};
+use clap::parser::ValueSource;
+use clap::{arg, command, value_parser, Arg, ArgMatches, Command};
+use clap_complete::Shell;
+use encoding_rs::Encoding;
+use icu_locale_core::LanguageIdentifier;
+use par_calc::{Balance, ChunkSize, Engine, SortKey, Split, TimeUnit, Unit};
+use crate::config::Config;
+use crate::style::{ColorChoice, Style};
+use crate::{Format, Histogram, Verbosity};
+
+// Upper bound on thread counts, so that a typo doesn't start thousands of threads
+pub const MAX_THREADS: u64 = 1024;
+
+fn thread_count() -> RangedU64ValueParser<usize> {
+    RangedU64ValueParser::new().range(1..=MAX_THREADS)
+}
+
+// For counts where zero would make the run meaningless
+fn parse_positive<T: FromStr<Err = ParseIntError> + From<u8> + PartialEq>(s: &str) -> Result<T, String> {
+    let n: T = s.parse().map_err(|e: ParseIntError| e.to_string())?;
+    if n == T::from(0) {
+        return Err("must be at least 1".to_string());
+    }
+    Ok(n)
+}
+
+// The privacy budget of --dp-epsilon, zero would mean infinite noise
+fn parse_epsilon(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(epsilon) if epsilon > 0.0 && epsilon.is_finite() => Ok(epsilon),
+        Ok(_) => Err("must be a positive number".to_string()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+fn parse_percent(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(percent) if percent > 0.0 && percent <= 100.0 => Ok(percent),
+        Ok(_) => Err("must be more than 0 and at most 100".to_string()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+// A number of characters to list, or None for "all" of them
+fn parse_rank(s: &str) -> Result<Option<usize>, String> {
+    if s == "all" { Ok(None) } else { parse_positive(s).map(Some) }
+}
+
+// A number of lines per queued chunk, or auto for par_calc::auto_chunk_lines
+fn parse_chunk_lines(s: &str) -> Result<ChunkSize, String> {
+    if s == "auto" { Ok(ChunkSize::Auto) } else { parse_positive(s).map(ChunkSize::Lines) }
+}
+
+// Parses "START:END", 1-indexed and inclusive
+fn parse_line_range(s: &str) -> Result<(usize, usize), String> {
+    let (start, end) = s.split_once(':').ok_or("expected START:END")?;
+    let start: usize = start.parse().map_err(|e| format!("invalid start: {e}"))?;
+    let end: usize = end.parse().map_err(|e| format!("invalid end: {e}"))?;
+    if start == 0 {
+        return Err("line numbers start at 1".to_string());
+    }
+    if start > end {
+        return Err(format!("start {start} is greater than end {end}"));
+    }
+    Ok((start, end))
+}
+
+// Which files to read and which of their lines to count
+fn input_args() -> Vec<Arg> {
+    vec![
+        arg!(<FILE>... "Files to operate on"),
+        arg!(--"line-range" <RANGE> "Only process lines START:END (1-indexed, inclusive)").value_parser(parse_line_range),
+        arg!(--loader <LOADER> "How to hold the input in memory, per-line allocates every line on its own like older versions").value_parser(["buffer", "per-line"]).default_value("buffer"),
+        arg!(--encoding <ENCODING> "Encoding of the input files").value_parser(["utf8", "utf16le", "utf16be", "latin1"]).default_value("utf8"),
+        arg!(--"skip-lines" <N> "Skip the first N lines, e.g. headers, after --line-range").value_parser(value_parser!(usize)),
+        arg!(--"skip-last" <N> "Skip the last N lines, after --line-range").value_parser(value_parser!(usize)),
+        arg!(--sample <N> "Count a random sample of N lines instead of the whole file").value_parser(parse_positive::<usize>),
+        arg!(--seed <SEED> "Seed for the random number generator").value_parser(value_parser!(u64)),
+        arg!(--"min-line-len" <N> "Ignore lines shorter than N characters").value_parser(value_parser!(usize)),
+        arg!(--"max-line-len" <N> "Ignore lines longer than N characters").value_parser(value_parser!(usize)),
+        arg!(--"fold-case" "Count upper and lower case as one character by lowercasing the input").overrides_with("no-fold-case"),
+        arg!(--"no-fold-case" "Count upper and lower case apart even if the config file sets fold_case").overrides_with("fold-case"),
+        arg!(--"no-whitespace" "Leave spaces, tabs and all other Unicode whitespace out of the counts"),
+        arg!(--locale <TAG> "Lowercase with the rules of this language, e.g. tr for the dotless i [default: Unicode default rules]")
+            .value_parser(|tag: &str| tag.parse::<LanguageIdentifier>().map_err(|e| e.to_string()))
+            .requires("fold-case"),
+        arg!(--"line-endings" "Also report how many lines of each file end in LF, CRLF and a lone CR"),
+        arg!(--"keep-going" "Warn about and skip files that can't be opened or read, counting the rest").overrides_with("fail-fast"),
+        arg!(--"fail-fast" "Stop at the first file that can't be opened or read, the default").overrides_with("keep-going"),
+        arg!(--"dry-run" "Only load the inputs and print what would be counted with which options"),
+    ]
+}
+
+fn threads_arg() -> Arg {
+    arg!(-t --threads <N> "Number of threads to count with [default: available parallelism]").value_parser(thread_count())
+}
+
+fn instrumented_arg() -> Arg {
+    arg!(--instrumented "Also report when the first and the last thread of the count had their result, to tell thread start-up from counting")
+        .conflicts_with("no-whitespace")
+}
+
+fn engine_arg() -> Arg {
+    arg!(--engine <ENGINE> "How to count the characters the stats are of")
+        .value_parser(PossibleValuesParser::new(Engine::available().into_iter().map(Engine::name)))
+        .default_value("parallel")
+        .conflicts_with("instrumented")
+}
+
+fn verify_arg() -> Arg {
+    arg!(--verify "Debugging aid: count again on one thread and fail, listing the differences, if that disagrees with the parallel count")
+        .conflicts_with("no-whitespace")
+        // The differences are exact counts
+        .conflicts_with("dp-epsilon")
+}
+
+fn bench_args() -> Vec<Arg> {
+    vec![
+        arg!(-m --max <MAX> "Benchmark every thread count up to MAX. This is an upper bound, inputs with fewer lines use fewer threads").value_parser(thread_count()).default_value("8"),
+        arg!(--"threads-fixed" <N> "Only benchmark with exactly N threads, giving extra threads empty chunks if the input is short").value_parser(thread_count()).conflicts_with("max"),
+        arg!(--"threads-csv" <LIST> "Only benchmark these thread counts, comma separated, e.g. 1,2,4,8")
+            .value_parser(thread_count())
+            .value_delimiter(',')
+            .conflicts_with_all(["max", "threads-fixed"]),
+        arg!(-r --reruns <RERUNS>  "The number of reruns to run each test").value_parser(parse_positive::<u32>).default_value("100"),
+        arg!(--balance <STRATEGY> "How to split the input between threads").value_parser(["lines", "chars"]).default_value("lines"),
+        arg!(--"chunk-lines" <N> "Split the input into chunks of N lines, or auto for about 8 per thread, that the threads take off a queue as they finish the last")
+            .value_parser(parse_chunk_lines)
+            .conflicts_with_all(["threads-fixed", "balance"]),
+        arg!(--imbalance "Report how many characters each thread counted").conflicts_with("dp-epsilon"),
+        arg!(--"repeat-forever" "Repeat the benchmark until Ctrl-C, printing the running mean and deviation of every thread count"),
+        arg!(--"summary-interval" <SECS> "Seconds between the summaries of --repeat-forever")
+            .value_parser(parse_positive::<u64>)
+            .default_value("60")
+            .requires("repeat-forever"),
+        arg!(--"compare-strategies" "Time every counting engine, serial included, with the largest thread count instead of sweeping the thread counts")
+            .conflicts_with_all(["repeat-forever", "bench-json", "baseline"]),
+        arg!(--"percentile-benchmark" "Also report the p50, p90, p95 and p99 latency and the fastest and slowest rerun"),
+        arg!(--"time-unit" <UNIT> "Unit for the timings, auto uses the one that suits the fastest run").value_parser(["auto", "ns", "us", "ms", "s"]).default_value("auto"),
+        arg!(--"bench-json" <PATH> "Also write the timings of the whole sweep to PATH as JSON"),
+        arg!(--baseline <JSON> "Compare the sweep with one saved with --bench-json and fail if a thread count got slower"),
+        arg!(--"regression-threshold" <PCT> "How many percent slower than --baseline counts as a regression")
+            .value_parser(value_parser!(f64))
+            .default_value("5")
+            .requires("baseline"),
+        arg!(--"no-bench" "Count once with all available threads instead of benchmarking").visible_aliases(["no-benchmark", "count-only"]),
+        arg!(--legacy).hide(true),
+    ]
+}
+
+// How the counts are reported
+fn report_args() -> Vec<Arg> {
+    vec![
+        arg!(--title <LABEL> "Label the output, in machine-readable formats every record gets it [default: the file names]"),
+        arg!(--"per-file" "Also report each file separately before the combined stats, csv, tsv and jsonl records name their file"),
+        arg!(-f --format <FORMAT> "Output format of the character statistics").value_parser(["human", "csv", "json", "jsonl", "tsv"]).default_value("human"),
+        arg!(--tsv "Same as --format tsv: CODEPOINT, CHAR and COUNT separated by tabs, bench timings as THREADS and MEAN_NS")
+            .visible_alias("tab-separated")
+            .conflicts_with("format"),
+        arg!(-o --output <PATH> "Write the character statistics to a file instead of stdout, - is stdout"),
+        arg!(--"save-results" <PATH> "Also save the counts to PATH as JSON, for reporting on them again with stats --load"),
+        arg!(--"append-results" <PATH> "Append the character statistics to a file, each run after a comment line with the time and title").conflicts_with("output"),
+        arg!(--"sort-by" <KEY> "Order of the most frequent characters listing")
+            .value_parser(["count-desc", "count-asc", "char-asc", "char-desc", "codepoint-asc", "codepoint-desc"])
+            .default_value("count-desc")
+            .visible_alias("output-sorted-by"),
+        arg!(--collate <ORDER> "How the char sort keys order characters, codepoint goes by scalar value, alphabetic keeps case pairs and accented letters together")
+            .value_parser(["codepoint", "alphabetic"])
+            .default_value("codepoint"),
+        arg!(--tiebreak <ORDER> "How characters with the same count are ordered").value_parser(["codepoint", "first-seen"]).default_value("codepoint"),
+        arg!(--histogram "Draw a bar next to each character in the stats listing"),
+        arg!(--"histogram-log" "Like --histogram, but scale the bars logarithmically"),
+        arg!(--"ascii-bars" "Draw histogram bars with '#' instead of block characters"),
+        arg!(--"format-number" "Print counts with thousands separators"),
+        arg!(--"print-codepoints" "Show the code point and Unicode name of every character in the stats listing, e.g. U+002D '-' (HYPHEN-MINUS)")
+            .visible_alias("print-char-codepoints"),
+        arg!(--"zero-pad" "Line up the counts of the stats listing, padding them with leading zeros to the widest one"),
+        arg!(--"pad-missing" "List exactly as many rows as asked for, filling in placeholders when there are fewer distinct characters"),
+        arg!(--"no-summary" "Don't print the total line and character counts"),
+        arg!(--compare <OTHER_FILE> "Compare the character distribution against another file"),
+        arg!(--threshold <PP> "Minimum difference in percentage points to report with --compare").value_parser(value_parser!(f64)).default_value("0.1"),
+        arg!(--reference <PATH> "Compare against expected frequencies from a CSV/JSON file, or 'english'"),
+        arg!(--unit <UNITS> "Also count these units, comma separated, in the same pass").value_parser(["grapheme", "char", "byte"]).value_delimiter(','),
+        arg!(--"max-keys" <N> "Keep at most N graphemes in memory, spilling rare ones to a temporary file; the top counts stay exact but part of the rare tail is lost").value_parser(parse_positive::<usize>).requires("unit"),
+        arg!(--tiers "Also show how many distinct characters occur once, 2-10 times, 11-100 times and so on"),
+        arg!(--"dp-epsilon" <EPSILON> "Add Laplace noise of scale 1/EPSILON to the counts for differential privacy, smaller is noisier; --seed makes it repeatable")
+            .value_parser(parse_epsilon)
+            // They count the raw input again, which would give the exact counts away
+            .conflicts_with_all(["cooccur", "top-lines", "unit"]),
+        arg!(--"by-script" "Also show how many of the characters belong to each Unicode script, e.g. Latin, Cyrillic or Han"),
+        arg!(--cooccur "Also list the pairs of characters that most often appear on the same line, as many as --stats"),
+        arg!(--"top-lines" <N> "Also list the N lines with the most occurrences of the most frequent character, numbered among the lines that were counted")
+            .value_parser(parse_positive::<usize>),
+        arg!(--concentration "Also show how many of the most frequent characters cover 50%, 80%, 95% and 99% of all occurrences"),
+    ]
+}
+
+// Only on count and stats, benchmarking again on every save would mostly measure the editor
+fn watch_arg() -> Arg {
+    arg!(-w --watch "After the report, keep watching the input files and report again whenever they change, until Ctrl-C")
+        .requires("FILE")
+        .conflicts_with("dry-run")
+}
+
+fn window_arg() -> Arg {
+    arg!(--window <N> "Report on every N lines on their own instead of on all of them, numbered across the input files")
+        .value_parser(parse_positive::<usize>)
+        .conflicts_with("per-file")
+}
+
+fn stats_arg() -> Arg {
+    arg!(-s --stats <rank>   "Display statistics for the top rank characters, or all of them").value_parser(parse_rank)
+}
+
+pub fn build_cli() -> Command {
+    command!() // requires `cargo` feature
+    .subcommand_required(true)
+    .arg(arg!(-q --quiet "Only print the requested output and errors").global(true))
+    .arg(arg!(-v --verbose "Also print how the input was loaded and split").global(true).conflicts_with("quiet"))
+    .arg(arg!(--color <WHEN> "Color the human-readable output, auto colors a terminal unless NO_COLOR is set").value_parser(["auto", "always", "never"]).default_value("auto").global(true))
+    .arg(arg!(--config <PATH> "Read option defaults from PATH [default: rust-lab9.toml if it exists]").global(true))
+    .arg(arg!(--"print-config" "Print the settings a config file can change and where they came from, then exit").global(true))
+    .subcommand(
+        Command::new("count")
+            .about("Count the characters once and report on them")
+            .args(input_args())
+            .arg(threads_arg())
+            .arg(instrumented_arg())
+            .arg(engine_arg())
+            .arg(verify_arg())
+            .arg(stats_arg())
+            .arg(watch_arg())
+            .arg(window_arg())
+            .args(report_args()),
+    )
+    .subcommand(
+        Command::new("bench")
+            .about("Benchmark the parallel counter for every thread count up to --max")
+            .args(input_args())
+            .args(bench_args())
+            .arg(instrumented_arg())
+            .arg(engine_arg())
+            .arg(verify_arg())
+            .arg(stats_arg())
+            .args(report_args()),
+    )
+    .subcommand(
+        Command::new("stats")
+            .about("Count the characters and list the most and least frequent ones")
+            .args(input_args())
+            .mut_arg("FILE", |arg| arg.required(false).required_unless_present_any(["load", "load-report"]))
+            .arg(arg!(--load <PATH> "Report on counts saved with --save-results instead of counting files").conflicts_with("FILE"))
+            .arg(
+                arg!(--"load-report" <PATH> "Report on a report written with --format json instead of counting files")
+                    .conflicts_with_all(["FILE", "load"]),
+            )
+            .arg(threads_arg())
+            .arg(arg!(--top <N> "Number of most frequent characters to list").value_parser(parse_positive::<usize>).default_value("10"))
+            .arg(
+                arg!(--"top-percent" <P> "List the most frequent characters that together make up P% of all occurrences instead")
+                    .value_parser(parse_percent)
+                    .conflicts_with("top"),
+            )
+            .arg(arg!(--bottom <N> "Number of least frequent characters to list").value_parser(parse_positive::<usize>))
+            .arg(watch_arg())
+            .arg(window_arg())
+            .args(report_args()),
+    )
+    .subcommand(
+        Command::new("serve")
+            .about("Keep counting lines from stdin or TCP clients and print the most frequent characters on request")
+            .arg(arg!(--listen <ADDR> "Count the lines sent by clients connecting to ADDR, stdin then takes the commands top and quit"))
+            .arg(arg!(--top <N> "Number of most frequent characters to print").value_parser(parse_positive::<usize>).default_value("10")),
+    )
+    .subcommand(
+        Command::new("compare")
+            .about("Compare two sets of counts saved with --save-results")
+            .arg(arg!(<SAVED> "The two files saved with --save-results").num_args(2))
+            .arg(arg!(-s --stats <N> "Only list the N characters whose count changed the most, all lists every one").value_parser(parse_rank))
+            .arg(arg!(--threshold <SIMILARITY> "Exit with 1 if the cosine similarity of the two is below SIMILARITY").value_parser(value_parser!(f64)))
+            .arg(arg!(--"format-number" "Print counts with thousands separators")),
+    )
+    .subcommand(
+        Command::new("verify")
+            .about("Check that the parallel counter agrees with the serial one on random text, for every thread count up to --max")
+            .arg(arg!(-m --max <MAX> "Check every thread count up to MAX").value_parser(thread_count()).default_value("8"))
+            .arg(arg!(--lines <N> "Number of random lines to count").value_parser(parse_positive::<usize>).default_value("1000"))
+            .arg(arg!(--seed <SEED> "Seed for the random text, printed when not given").value_parser(value_parser!(u64))),
+    )
+    .subcommand(
+        Command::new("completions")
+            .about("Print the shell completion script for SHELL")
+            .arg(arg!(<SHELL> "Shell to complete in").value_parser(value_parser!(Shell))),
+    )
+}
+
+// Invocations from before the subcommands existed (`par_calc FILE -m 4 ...`) run `bench`, which
+// only benchmarks for them if --max or --reruns is given
+pub fn compat_args(mut args: Vec<OsString>) -> Vec<OsString> {
+    let known = ["count", "bench", "stats", "serve", "compare", "verify", "completions", "help", "-h", "--help", "-V", "--version"];
+    if args.get(1).is_some_and(|first| !known.iter().any(|k| first == k)) {
+        eprintln!("note: no subcommand given, running `bench`. This form is deprecated, use `count` or `bench` explicitly.");
+        args.splice(1..1, ["bench".into(), "--legacy".into()]);
+    }
+    args
+}
+
+pub fn explicit(matches: &ArgMatches, id: &str) -> bool {
+    matches.try_get_raw(id).is_ok() && matches.value_source(id) == Some(ValueSource::CommandLine)
+}
+
+fn get<T: Clone + Send + Sync + 'static>(matches: &ArgMatches, id: &str) -> Option<T> {
+    matches.try_get_one::<T>(id).ok().flatten().cloned()
+}
+
+fn flag(matches: &ArgMatches, id: &str) -> bool {
+    get::<bool>(matches, id).unwrap_or(false)
+}
+
+// Command line over config file over built-in default, None if the subcommand has no such argument
+fn layered<T: Clone + Send + Sync + 'static>(matches: &ArgMatches, id: &str, config: Option<T>) -> Option<T> {
+    let value = matches.try_get_one::<T>(id).ok()?.cloned();
+    if explicit(matches, id) { value } else { config.or(value) }
+}
+
+#[derive(Debug)]
+pub struct Options {
+    pub verbosity: Verbosity,
+    pub style: Style,
+    pub bench: bool,
+    pub max_threads: usize,
+    pub reruns: u32,
+    pub range: Option<(usize, usize)>,
+    // None for UTF-8, which is read line by line rather than decoded as a whole
+    pub encoding: Option<&'static Encoding>,
+    pub keep_going: bool,
+    pub line_endings: bool,
+    // --loader per-line, one String per line instead of the one buffer of par_calc::Lines
+    pub per_line: bool,
+    pub skip_first: usize,
+    pub skip_last: usize,
+    pub sample: Option<usize>,
+    pub seed: Option<u64>,
+    pub min_len: usize,
+    pub max_len: usize,
+    pub fold_case: bool,
+    pub no_whitespace: bool,
+    pub locale: Option<LanguageIdentifier>,
+    pub split: Split,
+    pub sweep: Vec<usize>,
+    pub show_work: bool,
+    pub percentiles: bool,
+    pub repeat_forever: bool,
+    pub compare_strategies: bool,
+    pub summary_interval: Duration,
+    pub time_unit: Option<TimeUnit>,
+    pub bench_json: Option<String>,
+    pub baseline: Option<String>,
+    pub regression_threshold: f64,
+    pub save_results: Option<String>,
+    pub title: Option<String>,
+    pub per_file: bool,
+    pub group: bool,
+    pub zero_pad: bool,
+    pub codepoints: bool,
+    pub instrumented: bool,
+    pub engine: Engine,
+    pub verify: bool,
+    pub pad_missing: bool,
+    pub histogram: Option<Histogram>,
+    pub format: Format,
+    pub rank: Option<usize>,
+    pub top_percent: Option<f64>,
+    pub sort: SortKey,
+    pub first_seen: bool,
+    pub bottom: Option<usize>,
+    pub summary: bool,
+    pub compare: Option<String>,
+    pub threshold: f64,
+    pub reference: Option<String>,
+    pub units: Vec<Unit>,
+    pub max_keys: Option<usize>,
+    pub tiers: bool,
+    pub concentration: bool,
+    pub cooccur: bool,
+    pub top_lines: Option<usize>,
+    pub by_script: bool,
+    pub dp_epsilon: Option<f64>,
+    pub watch: bool,
+    pub window: Option<usize>,
+}
+
+impl Options {
+    // Takes the matches of whichever subcommand ran, arguments it doesn't have are left at their defaults
+    pub fn from_matches(matches: &ArgMatches, config: &Config) -> Options {
+        let verbosity = match (flag(matches, "quiet"), flag(matches, "verbose")) {
+            (true, _) => Verbosity::Quiet,
+            (_, true) => Verbosity::Verbose,
+            _ => Verbosity::Normal,
+        };
+        let bench = get::<u32>(matches, "reruns").is_some()
+            && !flag(matches, "no-bench")
+            && (!flag(matches, "legacy") || explicit(matches, "max") || explicit(matches, "reruns") || explicit(matches, "threads-fixed") || explicit(matches, "threads-csv"));
+        let fixed_threads = get::<usize>(matches, "threads-fixed");
+        let listed: Option<Vec<usize>> = matches.try_get_many::<usize>("threads-csv").ok().flatten().map(|n| n.copied().collect());
+        let sweep = match (fixed_threads, listed) {
+            (Some(n), _) => vec![n],
+            (_, Some(listed)) => listed,
+            _ => (1..=layered(matches, "max", config.max).unwrap_or(1)).collect(),
+        };
+        let max_threads = match layered(matches, "threads", config.threads) {
+            Some(threads) => threads,
+            None if bench => sweep.iter().copied().max().unwrap_or(1),
+            None => thread::available_parallelism().map_or(1, |n| n.get()),
+        };
+        let reruns = layered(matches, "reruns", config.reruns).unwrap_or(1);
+        let format = match get::<String>(matches, "format").as_deref() {
+            _ if flag(matches, "tsv") => Format::Tsv,
+            Some("csv") => Format::Csv,
+            Some("json") => Format::Json,
+            Some("jsonl") => Format::Jsonl,
+            Some("tsv") => Format::Tsv,
+            _ => Format::Human,
+        };
+        Options {
+            verbosity,
+            style: Style::new(match get::<String>(matches, "color").as_deref() {
+                Some("always") => ColorChoice::Always,
+                Some("never") => ColorChoice::Never,
+                _ => ColorChoice::Auto,
+            }),
+            bench,
+            max_threads,
+            reruns,
+            range: get(matches, "line-range"),
+            keep_going: flag(matches, "keep-going"),
+            line_endings: flag(matches, "line-endings"),
+            per_line: get::<String>(matches, "loader").as_deref() == Some("per-line"),
+            encoding: match get::<String>(matches, "encoding").as_deref() {
+                Some("utf16le") => Some(encoding_rs::UTF_16LE),
+                Some("utf16be") => Some(encoding_rs::UTF_16BE),
+                // What web browsers and encoding_rs read ISO-8859-1 as, a superset of it
+                Some("latin1") => Some(encoding_rs::WINDOWS_1252),
+                _ => None,
+            },
+            skip_first: get(matches, "skip-lines").unwrap_or(0),
+            skip_last: get(matches, "skip-last").unwrap_or(0),
+            sample: get(matches, "sample"),
+            seed: get(matches, "seed"),
+            min_len: get(matches, "min-line-len").unwrap_or(0),
+            max_len: get(matches, "max-line-len").unwrap_or(usize::MAX),
+            fold_case: !explicit(matches, "no-fold-case") && layered(matches, "fold-case", config.fold_case).unwrap_or(false),
+            no_whitespace: flag(matches, "no-whitespace"),
+            locale: get(matches, "locale"),
+            split: Split {
+                balance: match get::<String>(matches, "balance").as_deref() {
+                    Some("chars") => Balance::Chars,
+                    _ => Balance::Lines,
+                },
+                exact: fixed_threads.is_some(),
+                chunk_size: get(matches, "chunk-lines").unwrap_or_default(),
+            },
+            sweep,
+            show_work: flag(matches, "imbalance"),
+            percentiles: flag(matches, "percentile-benchmark"),
+            repeat_forever: flag(matches, "repeat-forever"),
+            compare_strategies: flag(matches, "compare-strategies"),
+            summary_interval: Duration::from_secs(get(matches, "summary-interval").unwrap_or(60)),
+            time_unit: match get::<String>(matches, "time-unit").as_deref() {
+                Some("ns") => Some(TimeUnit::Ns),
+                Some("us") => Some(TimeUnit::Us),
+                Some("ms") => Some(TimeUnit::Ms),
+                Some("s") => Some(TimeUnit::S),
+                _ => None,
+            },
+            bench_json: get(matches, "bench-json"),
+            baseline: get(matches, "baseline"),
+            regression_threshold: get(matches, "regression-threshold").unwrap_or(5.0),
+            save_results: get(matches, "save-results"),
+            title: get(matches, "title"),
+            per_file: flag(matches, "per-file"),
+            group: flag(matches, "format-number"),
+            zero_pad: flag(matches, "zero-pad"),
+            codepoints: flag(matches, "print-codepoints"),
+            instrumented: flag(matches, "instrumented"),
+            engine: match get::<String>(matches, "engine") {
+                Some(name) if explicit(matches, "engine") => Engine::from_name(&name).unwrap_or(Engine::Parallel),
+                Some(name) => config.engine.or(Engine::from_name(&name)).unwrap_or(Engine::Parallel),
+                None => Engine::Parallel,
+            },
+            verify: flag(matches, "verify"),
+            pad_missing: flag(matches, "pad-missing"),
+            histogram: (flag(matches, "histogram") || flag(matches, "histogram-log")).then(|| Histogram {
+                log: flag(matches, "histogram-log"),
+                ascii: flag(matches, "ascii-bars"),
+            }),
+            format: if explicit(matches, "format") || flag(matches, "tsv") { format } else { config.format.unwrap_or(format) },
+            top_percent: get(matches, "top-percent"),
+            rank: get::<Option<usize>>(matches, "stats").map(|rank| rank.unwrap_or(usize::MAX)).or(get::<usize>(matches, "top")),
+            sort: match (get::<String>(matches, "sort-by").as_deref(), get::<String>(matches, "collate").as_deref()) {
+                (Some("count-asc"), _) => SortKey::CountAsc,
+                (Some("char-asc"), Some("alphabetic")) => SortKey::CollatedAsc,
+                (Some("char-desc"), Some("alphabetic")) => SortKey::CollatedDesc,
+                (Some("char-asc"), _) => SortKey::CharAsc,
+                (Some("char-desc"), _) => SortKey::CharDesc,
+                (Some("codepoint-asc"), _) => SortKey::CodepointAsc,
+                (Some("codepoint-desc"), _) => SortKey::CodepointDesc,
+                _ => SortKey::CountDesc,
+            },
+            first_seen: get::<String>(matches, "tiebreak").as_deref() == Some("first-seen"),
+            bottom: get(matches, "bottom"),
+            summary: !flag(matches, "no-summary"),
+            compare: get(matches, "compare"),
+            threshold: get(matches, "threshold").unwrap_or(0.1),
+            reference: get(matches, "reference"),
+            tiers: flag(matches, "tiers"),
+            concentration: flag(matches, "concentration"),
+            cooccur: flag(matches, "cooccur"),
+            top_lines: get(matches, "top-lines"),
+            by_script: flag(matches, "by-script"),
+            dp_epsilon: get(matches, "dp-epsilon"),
+            watch: flag(matches, "watch"),
+            window: get(matches, "window"),
+            max_keys: get(matches, "max-keys"),
+            units: matches
+                .try_get_many::<String>("unit")
+                .ok()
+                .flatten()
+                .into_iter()
+                .flatten()
+                .map(|unit| match unit.as_str() {
+                    "grapheme" => Unit::Grapheme,
+                    "byte" => Unit::Byte,
+                    _ => Unit::Char,
+                })
+                .collect(),
+        }
+    }
+}
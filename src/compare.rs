@@ -0,0 +1,51 @@
+// The compare subcommand: puts two files saved with --save-results side by side
+use par_calc::{cosine_similarity, entropy, CharCounter};
+use crate::cli::Options;
+use crate::error::Error;
+use crate::{display_char, fmt_num, saved};
+
+// Change from `a` to `b` relative to `a`, None for a character only `b` has
+fn relative(a: u64, b: u64) -> Option<f64> {
+    (a > 0).then(|| (b as f64 - a as f64) * 100.0 / a as f64)
+}
+
+// One row per character, the largest absolute change first
+fn format_table(a: &CharCounter, b: &CharCounter, names: [&str; 2], opts: &Options) -> String {
+    let mut chars: Vec<char> = a.keys().chain(b.keys()).copied().collect();
+    chars.sort_unstable();
+    chars.dedup();
+    let count = |counts: &CharCounter, c: char| counts.get(&c).copied().unwrap_or(0);
+    chars.sort_by_key(|&c| std::cmp::Reverse(count(a, c).abs_diff(count(b, c))));
+    chars.truncate(opts.rank.unwrap_or(usize::MAX));
+
+    let mut rows = vec![["char".to_string(), names[0].to_string(), names[1].to_string(), "delta".to_string()]];
+    for c in chars {
+        let (n, m) = (count(a, c), count(b, c));
+        let sign = if m < n { "-" } else { "+" };
+        let delta = match relative(n, m) {
+            Some(pct) => format!("{sign}{} ({pct:+.2}%)", fmt_num(n.abs_diff(m), opts.group)),
+            None => format!("{sign}{} (new)", fmt_num(m, opts.group)),
+        };
+        rows.push([display_char(c), fmt_num(n, opts.group), fmt_num(m, opts.group), delta]);
+    }
+    let width = |column: usize| rows.iter().map(|row| row[column].chars().count()).max().unwrap_or(0);
+    let widths = [width(0), width(1), width(2)];
+    rows.iter()
+        .map(|[c, n, m, delta]| format!("{c:<w0$}  {n:>w1$}  {m:>w2$}  {delta}\n", w0 = widths[0], w1 = widths[1], w2 = widths[2]))
+        .collect()
+}
+
+// Fails if the similarity is below `threshold`, after printing everything
+pub fn compare(paths: [&str; 2], threshold: Option<f64>, opts: &Options) -> Result<(), Error> {
+    let (_, a) = saved::load(paths[0])?;
+    let (_, b) = saved::load(paths[1])?;
+    print!("{}", format_table(&a, &b, paths, opts));
+    let similarity = cosine_similarity(&a, &b);
+    let (entropy_a, entropy_b) = (entropy(&a), entropy(&b));
+    println!("Cosine similarity: {similarity:.6}");
+    println!("Entropy: {entropy_a:.4} vs {entropy_b:.4} bits per character, difference {:+.4}", entropy_b - entropy_a);
+    match threshold {
+        Some(threshold) if similarity < threshold => Err(Error::Dissimilar(similarity, threshold)),
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,99 @@
+// Defaults for the common options from a TOML file, which the command line overrides
+use std::fs;
+use std::path::Path;
+use clap::ArgMatches;
+use par_calc::Engine;
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer};
+use crate::cli::{explicit, Options, MAX_THREADS};
+use crate::error::Error;
+use crate::{invalid_data, Format};
+
+// Picked up from the working directory when --config isn't given
+pub const DEFAULT_PATH: &str = "rust-lab9.toml";
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    #[serde(skip)]
+    pub path: Option<String>,
+    #[serde(default, deserialize_with = "thread_count")]
+    pub threads: Option<usize>,
+    #[serde(default, deserialize_with = "thread_count")]
+    pub max: Option<usize>,
+    #[serde(default, deserialize_with = "positive")]
+    pub reruns: Option<u32>,
+    pub format: Option<Format>,
+    #[serde(default, deserialize_with = "engine")]
+    pub engine: Option<Engine>,
+    pub fold_case: Option<bool>,
+}
+
+fn thread_count<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<usize>, D::Error> {
+    let n = u64::deserialize(deserializer)?;
+    if !(1..=MAX_THREADS).contains(&n) {
+        return Err(D::Error::custom(format!("{n} is not in 1..={MAX_THREADS}")));
+    }
+    Ok(Some(n as usize))
+}
+
+fn positive<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<u32>, D::Error> {
+    match u32::deserialize(deserializer)? {
+        0 => Err(D::Error::custom("must be at least 1")),
+        n => Ok(Some(n)),
+    }
+}
+
+// One of the engines of this build, by the name --engine takes
+fn engine<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Engine>, D::Error> {
+    let name = String::deserialize(deserializer)?;
+    match Engine::from_name(&name) {
+        Some(engine) => Ok(Some(engine)),
+        None => {
+            let names: Vec<_> = Engine::available().into_iter().map(Engine::name).collect();
+            Err(D::Error::custom(format!("unknown engine '{name}', expected one of {}", names.join(", "))))
+        }
+    }
+}
+
+// An explicitly given file has to exist, the default one is optional
+pub fn load(path: Option<&str>) -> Result<Config, Error> {
+    let path = match path {
+        Some(path) => path,
+        None if Path::new(DEFAULT_PATH).exists() => DEFAULT_PATH,
+        None => return Ok(Config::default()),
+    };
+    let text = fs::read_to_string(path).map_err(|e| Error::open(path, e))?;
+    let mut config: Config = toml::from_str(&text).map_err(|e| invalid_data(format!("{path}: {e}")))?;
+    config.path = Some(path.to_string());
+    Ok(config)
+}
+
+// The settings the config file can change, with where each value came from
+pub fn print_effective(matches: &ArgMatches, config: &Config, opts: &Options) {
+    let source = |id: &str, in_config: bool| match &config.path {
+        _ if explicit(matches, id) => "command line",
+        Some(path) if in_config => path,
+        _ => "default",
+    };
+    let has = |id: &str| matches.try_get_raw(id).is_ok();
+    if has("threads") {
+        println!("threads = {}  # {}", opts.max_threads, source("threads", config.threads.is_some()));
+    }
+    if has("max") {
+        println!("max = {}  # {}", opts.max_threads, source("max", config.max.is_some()));
+    }
+    if has("reruns") {
+        println!("reruns = {}  # {}", opts.reruns, source("reruns", config.reruns.is_some()));
+    }
+    if has("format") {
+        println!("format = \"{}\"  # {}", format!("{:?}", opts.format).to_lowercase(), source("format", config.format.is_some()));
+    }
+    if has("engine") {
+        println!("engine = \"{}\"  # {}", opts.engine.name(), source("engine", config.engine.is_some()));
+    }
+    if has("fold-case") {
+        let source = if explicit(matches, "no-fold-case") { "command line" } else { source("fold-case", config.fold_case.is_some()) };
+        println!("fold_case = {}  # {}", opts.fold_case, source);
+    }
+}
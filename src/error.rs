@@ -0,0 +1,100 @@
+// What main reports on stderr, with an exit code per kind of problem so scripts can tell them apart
+use std::fmt;
+use std::io;
+use par_calc::{describe, CountError, LoadError};
+
+// clap's usage errors, which it would otherwise exit with 2, the code for an input that can't be opened
+pub const USAGE: u8 = 64;
+
+// A parallel count `verify` found disagreeing with the serial one, the codes below it being taken
+// by inputs that can't be opened or read
+pub const MISMATCH: u8 = 4;
+
+// 128 + SIGINT, what shells report for a command stopped with Ctrl-C
+pub const INTERRUPTED: u8 = 130;
+
+#[derive(Debug)]
+pub enum Error {
+    Load(LoadError),
+    Count(CountError),
+    Write(String, io::Error),
+    InvalidData(String),
+    // Options that make no sense for the input they are given, exits like clap's usage errors
+    Usage(String),
+    // The thread counts `verify` found disagreeing with the serial count
+    Mismatch(Vec<usize>),
+    // Cosine similarity `compare` found, and the --threshold it is below
+    Dissimilar(f64, f64),
+    // Thread counts --baseline found slower by more than the threshold, in percent
+    Regression(Vec<usize>, f64),
+    // Ctrl-C during bench, after the partial results were reported
+    Interrupted,
+    Io(io::Error),
+}
+
+impl Error {
+    // Reading a whole file fails on invalid UTF-8 too, which is bad data rather than a missing file
+    pub fn open(path: &str, e: io::Error) -> Error {
+        match e.kind() {
+            io::ErrorKind::InvalidData => Error::Load(LoadError::Read(path.to_string(), e)),
+            _ => Error::Load(LoadError::Open(path.to_string(), e)),
+        }
+    }
+
+    pub fn write(path: &str, e: io::Error) -> Error {
+        Error::Write(path.to_string(), e)
+    }
+
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            Error::Load(LoadError::Open(..)) => 2,
+            // EX_SOFTWARE, a bug rather than anything wrong with the input
+            Error::Count(_) => 70,
+            Error::Load(LoadError::Read(..)) | Error::InvalidData(_) => 3,
+            Error::Load(LoadError::PastEnd(..) | LoadError::InvalidRange(..)) | Error::Usage(_) => USAGE,
+            Error::Mismatch(_) => MISMATCH,
+            Error::Interrupted => INTERRUPTED,
+            Error::Write(..) | Error::Dissimilar(..) | Error::Regression(..) | Error::Io(_) => 1,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Load(e) => write!(f, "{e}"),
+            Error::Count(e) => write!(f, "{e}, no results were kept"),
+            Error::Write(path, e) => write!(f, "cannot write '{path}': {}", describe(e)),
+            Error::InvalidData(message) | Error::Usage(message) => write!(f, "{message}"),
+            Error::Mismatch(threads) => {
+                let threads: Vec<String> = threads.iter().map(usize::to_string).collect();
+                write!(f, "the parallel count differs from the serial one with {} threads", threads.join(", "))
+            }
+            Error::Dissimilar(similarity, threshold) => write!(f, "cosine similarity {similarity:.6} is below the threshold {threshold}"),
+            Error::Regression(threads, threshold) => {
+                let threads: Vec<String> = threads.iter().map(usize::to_string).collect();
+                write!(f, "more than {threshold}% slower than the baseline with {} threads", threads.join(", "))
+            }
+            Error::Interrupted => write!(f, "interrupted, the benchmark results are partial"),
+            Error::Io(e) => write!(f, "{}", describe(e)),
+        }
+    }
+}
+
+impl From<LoadError> for Error {
+    fn from(e: LoadError) -> Error {
+        Error::Load(e)
+    }
+}
+
+impl From<CountError> for Error {
+    fn from(e: CountError) -> Error {
+        Error::Count(e)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        Error::Io(e)
+    }
+}
@@ -0,0 +1,2245 @@
+//! Counting character occurrences in text, on one or several threads.
+//!
+//! ```
+//! use par_calc::count_chars;
+//!
+//! let lines = ["hello", "wörld", ""];
+//! let counts = count_chars(&lines);
+//! assert_eq!(counts[&'l'], 3);
+//! assert_eq!(counts[&'ö'], 1);
+//! #[cfg(feature = "parallel")]
+//! assert_eq!(counts, par_calc::count_chars_parallel(&lines, 2).unwrap());
+//! ```
+//!
+//! With the `bench` feature (enabled by the default `cli` feature), [`benchmark`] and
+//! [`benchmark_all`] time the parallel counter.
+
+use std::cmp::{max, min, Reverse};
+use std::collections::HashMap;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::ops::Range;
+use std::fs::File;
+use std::io::{self, BufRead};
+#[cfg(feature = "parallel")]
+use std::thread;
+#[cfg(feature = "bench")]
+use std::sync::atomic::{AtomicBool, Ordering};
+#[cfg(feature = "bench")]
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "ahash")]
+type BuildCharHasher = ahash::RandomState;
+#[cfg(not(feature = "ahash"))]
+type BuildCharHasher = std::collections::hash_map::RandomState;
+
+/// Character counts as returned by the counting functions, a map from character to count that
+/// derefs to the `HashMap` it wraps. The counts are `u64` whatever the pointer width, and merging
+/// with `+=`, `+` or `sum` saturates instead of wrapping. With the `ahash` feature the map uses
+/// a faster, non-DoS-resistant hasher.
+///
+/// ```
+/// use par_calc::CharCounter;
+///
+/// let mut counter: CharCounter = "abca".chars().collect();
+/// counter += "cd".chars().collect::<CharCounter>();
+/// assert_eq!((counter[&'a'], counter[&'c'], counter.total(), counter.distinct()), (2, 2, 6, 4));
+/// assert_eq!(counter.top_n(2), [('a', 2), ('c', 2)]);
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CharCounter(HashMap<char, u64, BuildCharHasher>);
+
+impl CharCounter {
+    /// An empty counter with room for `capacity` distinct characters.
+    pub fn with_capacity(capacity: usize) -> CharCounter {
+        CharCounter(HashMap::with_capacity_and_hasher(capacity, Default::default()))
+    }
+
+    /// Sum of all counts, saturating at `u64::MAX`.
+    pub fn total(&self) -> u64 {
+        self.values().fold(0, |total, &n| total.saturating_add(n))
+    }
+
+    /// Number of distinct characters.
+    pub fn distinct(&self) -> usize {
+        self.len()
+    }
+
+    /// The `n` most frequent characters, most frequent first and ties in code point order.
+    ///
+    /// ```
+    /// use par_calc::count_chars;
+    ///
+    /// assert_eq!(count_chars(&["bbaacd"]).top_n(3), [('a', 2), ('b', 2), ('c', 1)]);
+    /// ```
+    pub fn top_n(&self, n: usize) -> Vec<(char, u64)> {
+        self.sorted_by(n, |&(c, n)| (Reverse(n), c))
+    }
+
+    /// The `n` least frequent characters, least frequent first and ties in code point order.
+    ///
+    /// ```
+    /// use par_calc::count_chars;
+    ///
+    /// assert_eq!(count_chars(&["bbaadc"]).bottom_n(3), [('c', 1), ('d', 1), ('a', 2)]);
+    /// ```
+    pub fn bottom_n(&self, n: usize) -> Vec<(char, u64)> {
+        self.sorted_by(n, |&(c, n)| (n, c))
+    }
+
+    fn sorted_by<K: Ord>(&self, n: usize, mut key: impl FnMut(&(char, u64)) -> K) -> Vec<(char, u64)> {
+        smallest_by(self.iter().map(|(&c, &n)| (c, n)).collect(), n, |a, b| key(a).cmp(&key(b)))
+    }
+}
+
+/// The `n` keys with the highest counts, highest first and ties in key order, e.g. for the
+/// graphemes of [`count_units`] or the pairs of [`count_cooccurrences`]. Only those `n` are
+/// sorted, so asking for the top 10 of millions of keys takes about one pass over them.
+///
+/// ```
+/// use par_calc::top_counts;
+///
+/// assert_eq!(top_counts([("b", 2), ("c", 5), ("a", 2), ("d", 1)], 3), [("c", 5), ("a", 2), ("b", 2)]);
+/// ```
+pub fn top_counts<K: Ord>(counts: impl IntoIterator<Item = (K, u64)>, n: usize) -> Vec<(K, u64)> {
+    smallest_by(counts.into_iter().collect(), n, |a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)))
+}
+
+// The `n` first items in `order`, which has to tell all of them apart for the result not to
+// depend on the order they came in
+fn smallest_by<T>(mut items: Vec<T>, n: usize, mut order: impl FnMut(&T, &T) -> std::cmp::Ordering) -> Vec<T> {
+    if n == 0 {
+        return Vec::new();
+    }
+    if n < items.len() {
+        items.select_nth_unstable_by(n - 1, &mut order);
+        items.truncate(n);
+    }
+    items.sort_unstable_by(order);
+    items
+}
+
+impl std::ops::Deref for CharCounter {
+    type Target = HashMap<char, u64, BuildCharHasher>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl std::ops::DerefMut for CharCounter {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+impl std::ops::AddAssign<&CharCounter> for CharCounter {
+    fn add_assign(&mut self, part: &CharCounter) {
+        // The merged map has at least as many keys as the bigger of the two
+        let len = self.len();
+        self.reserve(part.len().saturating_sub(len));
+        self.extend(part.iter().map(|(&c, &n)| (c, n)));
+    }
+}
+
+impl std::ops::AddAssign for CharCounter {
+    fn add_assign(&mut self, part: CharCounter) {
+        *self += &part;
+    }
+}
+
+impl std::ops::Add for CharCounter {
+    type Output = CharCounter;
+
+    // Into the bigger of the two, which has less to insert
+    fn add(self, other: CharCounter) -> CharCounter {
+        let (mut big, small) = if self.len() >= other.len() { (self, other) } else { (other, self) };
+        big += &small;
+        big
+    }
+}
+
+impl std::iter::Sum for CharCounter {
+    fn sum<I: Iterator<Item = CharCounter>>(parts: I) -> CharCounter {
+        parts.fold(CharCounter::default(), |total, part| total + part)
+    }
+}
+
+/// Adds counts, saturating.
+impl Extend<(char, u64)> for CharCounter {
+    fn extend<I: IntoIterator<Item = (char, u64)>>(&mut self, counts: I) {
+        for (c, n) in counts {
+            let count = self.0.entry(c).or_default();
+            *count = count.saturating_add(n);
+        }
+    }
+}
+
+/// Counts each character once more.
+impl Extend<char> for CharCounter {
+    fn extend<I: IntoIterator<Item = char>>(&mut self, chars: I) {
+        for c in chars {
+            *self.0.entry(c).or_default() += 1;
+        }
+    }
+}
+
+impl FromIterator<char> for CharCounter {
+    fn from_iter<I: IntoIterator<Item = char>>(chars: I) -> CharCounter {
+        let mut counter = CharCounter::default();
+        counter.extend(chars);
+        counter
+    }
+}
+
+/// Adds up the counts of characters that occur more than once.
+impl FromIterator<(char, u64)> for CharCounter {
+    fn from_iter<I: IntoIterator<Item = (char, u64)>>(counts: I) -> CharCounter {
+        let mut counter = CharCounter::default();
+        counter.extend(counts);
+        counter
+    }
+}
+
+impl<S> From<HashMap<char, u64, S>> for CharCounter {
+    fn from(counts: HashMap<char, u64, S>) -> CharCounter {
+        counts.into_iter().collect()
+    }
+}
+
+impl From<CharCounter> for HashMap<char, u64> {
+    fn from(counter: CharCounter) -> HashMap<char, u64> {
+        counter.0.into_iter().collect()
+    }
+}
+
+impl IntoIterator for CharCounter {
+    type Item = (char, u64);
+    type IntoIter = std::collections::hash_map::IntoIter<char, u64>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a CharCounter {
+    type Item = (&'a char, &'a u64);
+    type IntoIter = std::collections::hash_map::Iter<'a, char, u64>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter()
+    }
+}
+
+/// Sum of all counts, saturating at `u64::MAX`.
+///
+/// ```
+/// use par_calc::{count_chars, total_chars};
+///
+/// assert_eq!(total_chars(&count_chars(&["abc", "é"])), 4);
+/// ```
+pub fn total_chars(counts: &CharCounter) -> u64 {
+    counts.total()
+}
+
+/// Why [`load_file`] failed. Every variant names the file it was loading.
+#[derive(Debug)]
+pub enum LoadError {
+    /// The file couldn't be opened.
+    Open(String, io::Error),
+    /// Reading failed part way through, for example on invalid UTF-8.
+    Read(String, io::Error),
+    /// The line range ends after the last line of the file.
+    PastEnd(String, usize),
+    /// The line range starts at 0 or after its end.
+    InvalidRange(String, usize, usize),
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadError::Open(path, e) => write!(f, "cannot open '{path}': {}", describe(e)),
+            LoadError::Read(path, e) => write!(f, "cannot read '{path}': {}", describe(e)),
+            LoadError::PastEnd(path, end) => write!(f, "line range end {end} is past the end of '{path}'"),
+            LoadError::InvalidRange(path, start, end) => write!(f, "invalid line range {start}:{end} for '{path}', lines start at 1 and the range can't end before it starts"),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            LoadError::Open(_, e) | LoadError::Read(_, e) => Some(e),
+            LoadError::PastEnd(..) | LoadError::InvalidRange(..) => None,
+        }
+    }
+}
+
+/// The message of an I/O error without the "(os error N)" the standard library appends.
+///
+/// ```
+/// use std::io;
+/// use par_calc::describe;
+///
+/// assert_eq!(describe(&io::Error::from_raw_os_error(2)), "No such file or directory");
+/// ```
+pub fn describe(e: &io::Error) -> String {
+    let text = e.to_string();
+    match e.raw_os_error() {
+        Some(code) => text.trim_end_matches(&format!(" (os error {code})")).to_string(),
+        None => text,
+    }
+}
+
+/// Reads the lines of a file, or only lines `start..=end` (1-indexed) of it if a range is given.
+pub fn load_file(name: &str, range: Option<(usize, usize)>) -> Result<Vec<String>, LoadError> {
+    let file = File::open(name).map_err(|e| LoadError::Open(name.to_string(), e))?;
+    let lines = io::BufReader::new(file).lines().map(|line| line.map_err(|e| LoadError::Read(name.to_string(), e)));
+    select_lines(name, lines, range)
+}
+
+/// How the lines of a text end, which [`load_file`] and the other loaders strip.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LineEndingStats {
+    pub crlf: usize,
+    pub lf: usize,
+    /// A "\r" on its own, the classic Mac OS line ending. The loaders don't split lines on it.
+    pub cr: usize,
+    /// More than one of the three occurs
+    pub mixed: bool,
+}
+
+/// Counts the line endings of `text`.
+///
+/// ```
+/// use par_calc::{line_endings, LineEndingStats};
+///
+/// let stats = line_endings(b"dos\r\nunix\nmac\rdos\r\nlast");
+/// assert_eq!(stats, LineEndingStats { crlf: 2, lf: 1, cr: 1, mixed: true });
+/// assert!(!line_endings(b"a\nb\n").mixed);
+/// ```
+pub fn line_endings(text: &[u8]) -> LineEndingStats {
+    let mut stats = LineEndingStats::default();
+    let mut bytes = text.iter().peekable();
+    while let Some(&b) = bytes.next() {
+        match b {
+            b'\r' if bytes.next_if_eq(&&b'\n').is_some() => stats.crlf += 1,
+            b'\r' => stats.cr += 1,
+            b'\n' => stats.lf += 1,
+            _ => {}
+        }
+    }
+    stats.mixed = [stats.crlf, stats.lf, stats.cr].iter().filter(|&&n| n > 0).count() > 1;
+    stats
+}
+
+/// Like [`load_file`], also counting the line endings of the whole file, not only of `range`.
+pub fn load_file_with_endings(name: &str, range: Option<(usize, usize)>) -> Result<(Vec<String>, LineEndingStats), LoadError> {
+    let bytes = std::fs::read(name).map_err(|e| LoadError::Open(name.to_string(), e))?;
+    let endings = line_endings(&bytes);
+    let text = String::from_utf8(bytes).map_err(|e| LoadError::Read(name.to_string(), io::Error::new(io::ErrorKind::InvalidData, e)))?;
+    Ok((select_lines(name, text.lines().map(|line| Ok(line.to_string())), range)?, endings))
+}
+
+/// The lines of a file kept in one buffer, as spans of it, instead of one allocation per line
+/// like [`load_file`]. [`Lines::strs`] gives the slice the counting functions take.
+///
+/// ```
+/// use par_calc::{count_chars, Lines};
+///
+/// let mut lines = Lines::from("one\r\ntwo\n\nthree".to_string());
+/// assert_eq!(lines.len(), 4);
+/// lines.retain(|line| !line.is_empty());
+/// assert_eq!(lines.strs(), ["one", "two", "three"]);
+/// assert_eq!(count_chars(&lines.strs())[&'e'], 3);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct Lines {
+    text: String,
+    spans: Vec<Range<usize>>,
+    // Of the lines in `spans`, kept up to date as lines are dropped instead of summed when asked for
+    bytes: usize,
+}
+
+impl Lines {
+    pub fn len(&self) -> usize {
+        self.spans.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.spans.is_empty()
+    }
+
+    /// Total length of the lines in bytes, without their line endings.
+    ///
+    /// ```
+    /// use par_calc::Lines;
+    ///
+    /// let mut lines = Lines::from("née\r\nab\n".to_string());
+    /// assert_eq!((lines.len(), lines.bytes()), (2, 6));
+    /// lines.retain(|line| line.is_ascii());
+    /// assert_eq!(lines.bytes(), 2);
+    /// ```
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    pub fn get(&self, index: usize) -> Option<&str> {
+        self.spans.get(index).map(|span| &self.text[span.clone()])
+    }
+
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &str> + ExactSizeIterator + '_ {
+        self.spans.iter().map(|span| &self.text[span.clone()])
+    }
+
+    /// The lines as string slices into the buffer, e.g. for [`count_chars_parallel`] to split.
+    pub fn strs(&self) -> Vec<&str> {
+        self.iter().collect()
+    }
+
+    /// The line endings of the whole buffer, including lines dropped since it was read.
+    pub fn line_endings(&self) -> LineEndingStats {
+        line_endings(self.text.as_bytes())
+    }
+
+    /// Keeps only the lines `f` returns true for. The buffer stays as it is.
+    pub fn retain(&mut self, mut f: impl FnMut(&str) -> bool) {
+        let (text, bytes) = (&self.text, &mut self.bytes);
+        self.spans.retain(|span| {
+            let keep = f(&text[span.clone()]);
+            if !keep {
+                *bytes -= span.len();
+            }
+            keep
+        });
+    }
+
+    /// Keeps only the lines at `indices`, in that order.
+    ///
+    /// Panics if an index is not below [`len`](Lines::len).
+    pub fn select(&mut self, indices: impl IntoIterator<Item = usize>) {
+        self.spans = indices.into_iter().map(|i| self.spans[i].clone()).collect();
+        self.bytes = self.spans.iter().map(Range::len).sum();
+    }
+}
+
+// Split like BufRead::lines, on "\n" with a "\r" before it dropped and no empty last line
+impl From<String> for Lines {
+    fn from(text: String) -> Lines {
+        // lines() only hands out subslices of `text`, so their offset in it gives the span
+        let mut bytes = 0;
+        let spans = text
+            .lines()
+            .map(|line| {
+                let offset = line.as_ptr() as usize - text.as_ptr() as usize;
+                bytes += line.len();
+                offset..offset + line.len()
+            })
+            .collect();
+        Lines { text, spans, bytes }
+    }
+}
+
+impl<S: AsRef<str>> FromIterator<S> for Lines {
+    fn from_iter<I: IntoIterator<Item = S>>(lines: I) -> Lines {
+        let mut all = Lines::default();
+        for line in lines {
+            let start = all.text.len();
+            all.text += line.as_ref();
+            all.spans.push(start..all.text.len());
+        }
+        all.bytes = all.text.len();
+        all
+    }
+}
+
+/// Like [`load_file`], reading the file in one go into a [`Lines`].
+pub fn load_lines(name: &str, range: Option<(usize, usize)>) -> Result<Lines, LoadError> {
+    let mut file = File::open(name).map_err(|e| LoadError::Open(name.to_string(), e))?;
+    let mut text = String::new();
+    io::Read::read_to_string(&mut file, &mut text).map_err(|e| LoadError::Read(name.to_string(), e))?;
+    select_spans(name, Lines::from(text), range)
+}
+
+/// Like [`load_lines`] for a file in another encoding, e.g. `encoding_rs::UTF_16LE`, dropping a
+/// byte order mark. Needs the `encodings` feature.
+#[cfg(feature = "encodings")]
+pub fn load_lines_as(name: &str, range: Option<(usize, usize)>, encoding: &'static encoding_rs::Encoding) -> Result<Lines, LoadError> {
+    let bytes = std::fs::read(name).map_err(|e| LoadError::Open(name.to_string(), e))?;
+    let (text, malformed) = encoding.decode_with_bom_removal(&bytes);
+    if malformed {
+        let e = io::Error::new(io::ErrorKind::InvalidData, format!("stream did not contain valid {}", encoding.name()));
+        return Err(LoadError::Read(name.to_string(), e));
+    }
+    select_spans(name, Lines::from(text.into_owned()), range)
+}
+
+// 1-indexed and inclusive, so 0 or a start after the end can't be a range
+fn check_range(name: &str, range: Option<(usize, usize)>) -> Result<(), LoadError> {
+    match range {
+        Some((start, end)) if start == 0 || start > end => Err(LoadError::InvalidRange(name.to_string(), start, end)),
+        _ => Ok(()),
+    }
+}
+
+fn select_spans(name: &str, mut lines: Lines, range: Option<(usize, usize)>) -> Result<Lines, LoadError> {
+    check_range(name, range)?;
+    if let Some((start, end)) = range {
+        if lines.len() < end {
+            return Err(LoadError::PastEnd(name.to_string(), end));
+        }
+        lines.select(start - 1..end);
+    }
+    Ok(lines)
+}
+
+fn select_lines(name: &str, lines: impl Iterator<Item = Result<String, LoadError>>, range: Option<(usize, usize)>) -> Result<Vec<String>, LoadError> {
+    check_range(name, range)?;
+    let Some((start, end)) = range else {
+        return lines.collect();
+    };
+    let slice: Vec<String> = lines.skip(start - 1).take(end - start + 1).collect::<Result<_, _>>()?;
+    if slice.len() < end - start + 1 {
+        return Err(LoadError::PastEnd(name.to_string(), end));
+    }
+    Ok(slice)
+}
+
+/// Counts the occurrences of every character in the input.
+pub fn count_chars<S: AsRef<str>>(input: &[S]) -> CharCounter {
+    // Enough for mostly-ASCII text without rehashing, bigger alphabets grow from there
+    let bytes: usize = input.iter().map(|text| text.as_ref().len()).sum();
+    let mut counter = CharCounter::with_capacity(min(bytes, 256));
+    for text in input {
+        counter.extend(text.as_ref().chars());
+    }
+    counter
+}
+
+/// Counts the characters of one text on the calling thread, in code point order. Needs no
+/// threads, so it is there on every target, `wasm32-unknown-unknown` included.
+///
+/// ```
+/// use par_calc::count_text;
+///
+/// let counts = count_text("abba\n🎉");
+/// assert_eq!(counts.into_iter().collect::<Vec<_>>(), [('\n', 1), ('a', 2), ('b', 2), ('🎉', 1)]);
+/// ```
+pub fn count_text(text: &str) -> BTreeMap<char, u64> {
+    let mut counts = BTreeMap::new();
+    for c in text.chars() {
+        *counts.entry(c).or_default() += 1;
+    }
+    counts
+}
+
+/// Like [`count_chars`], but also returns the distinct characters in the order they first appear.
+///
+/// ```
+/// use par_calc::count_chars_ordered;
+///
+/// let (counts, order) = count_chars_ordered(&["bab", "ca"]);
+/// assert_eq!(counts[&'a'], 2);
+/// assert_eq!(order, ['b', 'a', 'c']);
+/// ```
+pub fn count_chars_ordered<S: AsRef<str>>(input: &[S]) -> (CharCounter, Vec<char>) {
+    let mut counter = CharCounter::default();
+    let mut order = Vec::new();
+    for text in input {
+        for c in text.as_ref().chars() {
+            let n = counter.entry(c).or_default();
+            if *n == 0 {
+                order.push(c);
+            }
+            *n += 1;
+        }
+    }
+    (counter, order)
+}
+
+/// Counts the characters of any iterator, without collecting them into strings first.
+///
+/// ```
+/// use par_calc::{count_chars, count_chars_iter};
+///
+/// let counts = count_chars_iter("hello".chars().rev());
+/// assert_eq!(counts[&'l'], 2);
+/// assert_eq!(counts, count_chars(&["hello"]));
+/// ```
+pub fn count_chars_iter<I: IntoIterator<Item = char>>(chars: I) -> CharCounter {
+    chars.into_iter().collect()
+}
+
+/// Adds the characters of `text` to an existing count, for input that arrives piece by piece.
+pub fn count_into(counter: &mut CharCounter, text: &str) {
+    counter.extend(text.chars());
+}
+
+/// Like [`count_chars`], leaving out every character for which [`char::is_whitespace`] is true.
+/// Can be given to [`count_chunks_with`] to do the same on several threads.
+///
+/// ```
+/// use par_calc::count_non_whitespace;
+///
+/// let counts = count_non_whitespace(&["a b\tc", "\u{3000}a\u{a0}"]);
+/// assert_eq!((counts[&'a'], counts.len(), counts.total()), (2, 3, 4));
+/// ```
+pub fn count_non_whitespace<S: AsRef<str>>(input: &[S]) -> CharCounter {
+    let mut counter = CharCounter::default();
+    for line in input {
+        counter.extend(line.as_ref().chars().filter(|&c| !is_space(c)));
+    }
+    counter
+}
+
+// char::is_whitespace with the ASCII case, nearly every character of most text, answered first
+fn is_space(c: char) -> bool {
+    if c.is_ascii() { matches!(c, ' ' | '\t'..='\r') } else { c.is_whitespace() }
+}
+
+/// Counts the characters of any sequence of strings, e.g. lines from a generator, without
+/// collecting them into a slice first.
+///
+/// ```
+/// use par_calc::count_chars_from_strs;
+///
+/// let counts = count_chars_from_strs("a,bb,c".split(','));
+/// assert_eq!((counts[&'b'], counts.len()), (2, 3));
+/// ```
+pub fn count_chars_from_strs<I: IntoIterator<Item = S>, S: AsRef<str>>(strs: I) -> CharCounter {
+    let mut counter = CharCounter::default();
+    for text in strs {
+        count_into(&mut counter, text.as_ref());
+    }
+    counter
+}
+
+// The next line as BufRead::lines would return it, read into `line` to reuse its allocation, None
+// at the end. A line may be longer than the reader's buffer, read_until keeps growing `line`.
+fn next_line<'a, R: BufRead>(reader: &mut R, line: &'a mut Vec<u8>) -> io::Result<Option<&'a str>> {
+    line.clear();
+    if reader.read_until(b'\n', line)? == 0 {
+        return Ok(None);
+    }
+    let mut text = std::str::from_utf8(line).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    if let Some(rest) = text.strip_suffix('\n') {
+        text = rest.strip_suffix('\r').unwrap_or(rest);
+    }
+    Ok(Some(text))
+}
+
+/// Counts the lines of a reader as [`load_file`] would read them, so line breaks aren't counted.
+/// Read errors and invalid UTF-8 part way through are returned rather than counted around.
+///
+/// ```
+/// use std::io::Cursor;
+/// use par_calc::count_chars_from_reader;
+///
+/// let counts = count_chars_from_reader(Cursor::new("ab\r\nb\n".as_bytes())).unwrap();
+/// assert_eq!((counts[&'b'], counts.len()), (2, 2));
+/// assert!(count_chars_from_reader(Cursor::new(&b"ok\n\xff"[..])).is_err());
+/// ```
+pub fn count_chars_from_reader<R: BufRead>(mut reader: R) -> io::Result<CharCounter> {
+    let mut counter = CharCounter::default();
+    let mut line = Vec::new();
+    while let Some(text) = next_line(&mut reader, &mut line)? {
+        count_into(&mut counter, text);
+    }
+    Ok(counter)
+}
+
+/// How to split the input between threads.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Balance {
+    /// Equal number of lines per thread
+    #[default]
+    Lines,
+    /// Roughly equal number of characters per thread
+    Chars,
+}
+
+/// How [`split_input`] cuts the input into chunks for the threads.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Split {
+    pub balance: Balance,
+    /// Always use exactly `n` threads, giving the extra ones empty chunks when the input has
+    /// fewer than `n` lines. Otherwise `n` is only an upper bound.
+    pub exact: bool,
+    /// Whether the threads get one chunk each or take fixed-size ones off a queue.
+    pub chunk_size: ChunkSize,
+}
+
+/// The size of the chunks the threads count, see [`count_chunks_queued`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ChunkSize {
+    /// One chunk per thread, as split by [`Split::balance`]
+    #[default]
+    PerThread,
+    /// Chunks of this many lines, taken off a queue by however many threads are free
+    Lines(usize),
+    /// Chunks of [`auto_chunk_lines`] lines off a queue
+    Auto,
+}
+
+impl ChunkSize {
+    /// The number of lines per queued chunk for `len` lines on `n` threads, None for one chunk
+    /// per thread.
+    ///
+    /// ```
+    /// use par_calc::ChunkSize;
+    ///
+    /// assert_eq!(ChunkSize::PerThread.lines_for(100_000, 4), None);
+    /// assert_eq!(ChunkSize::Lines(10).lines_for(100_000, 4), Some(10));
+    /// assert_eq!(ChunkSize::Auto.lines_for(100_000, 4), Some(3125));
+    /// ```
+    pub fn lines_for(self, len: usize, n: usize) -> Option<usize> {
+        match self {
+            ChunkSize::PerThread => None,
+            ChunkSize::Lines(lines) => Some(max(lines, 1)),
+            ChunkSize::Auto => Some(auto_chunk_lines(len, n)),
+        }
+    }
+}
+
+/// Lines per chunk when they aren't given: about 8 chunks per thread so that a thread that falls
+/// behind holds up the others by an eighth of its share at most, but never fewer than 1024 lines
+/// so that taking a chunk off the queue stays cheap next to counting it.
+///
+/// ```
+/// use par_calc::auto_chunk_lines;
+///
+/// assert_eq!(auto_chunk_lines(1_000_000, 8), 15625);
+/// assert_eq!(auto_chunk_lines(10, 8), 1024);
+/// ```
+pub fn auto_chunk_lines(len: usize, n: usize) -> usize {
+    max(len / (normalize_threads(n) * 8), 1024)
+}
+
+/// The thread count the counters actually use for a requested `n`: asking for no threads gets one.
+///
+/// ```
+/// use par_calc::normalize_threads;
+///
+/// assert_eq!(normalize_threads(0), 1);
+/// assert_eq!(normalize_threads(8), 8);
+/// ```
+pub fn normalize_threads(n: usize) -> usize {
+    max(n, 1)
+}
+
+/// The number of runs the benchmarks actually time for a requested `reruns`, at least one.
+///
+/// ```
+/// use par_calc::normalize_reruns;
+///
+/// assert_eq!(normalize_reruns(0), 1);
+/// assert_eq!(normalize_reruns(5), 5);
+/// ```
+pub fn normalize_reruns(reruns: u32) -> u32 {
+    max(reruns, 1)
+}
+
+/// Splits the input into at most `n` chunks of roughly equal line count or character weight, or
+/// exactly `n` chunks if [`Split::exact`] is set.
+///
+/// ```
+/// use par_calc::{split_input, Split};
+///
+/// let lines = ["a", "b"];
+/// assert_eq!(split_input(&lines, 4, Split::default()).len(), 2);
+/// assert_eq!(split_input(&lines, 4, Split { exact: true, ..Split::default() }).len(), 4);
+/// ```
+pub fn split_input<S: AsRef<str>>(input: &[S], n: usize, split: Split) -> Vec<&[S]> {
+    let n = normalize_threads(n);
+    let mut chunks = split_by_balance(input, n, split.balance);
+    if split.exact {
+        chunks.resize(n, &[]);
+    }
+    chunks
+}
+
+fn split_by_balance<S: AsRef<str>>(input: &[S], n: usize, balance: Balance) -> Vec<&[S]> {
+    match balance {
+        Balance::Lines => input.chunks(max(input.len().div_ceil(n), 1)).collect(),
+        Balance::Chars => {
+            // Byte lengths are a good enough proxy for the counting work and are free to get
+            let mut prefix = Vec::with_capacity(input.len());
+            let mut total = 0;
+            for line in input {
+                total += line.as_ref().len();
+                prefix.push(total);
+            }
+            let mut chunks = Vec::with_capacity(n);
+            let mut start = 0;
+            for k in 1..=n {
+                let end = if k == n { input.len() } else { prefix.partition_point(|&sum| sum * n < total * k) + 1 };
+                let end = end.clamp(start, input.len());
+                if end > start {
+                    chunks.push(&input[start..end]);
+                }
+                start = end;
+            }
+            chunks
+        }
+    }
+}
+
+/// Splits UTF-8 `bytes` into at most `n` segments of roughly equal length, moving each cut forward
+/// to the next character boundary so that no character straddles two segments. Segments that
+/// would be empty after that are left out.
+///
+/// ```
+/// use par_calc::split_at_char_boundaries;
+///
+/// // The natural cuts at bytes 3 and 6 fall inside the two-byte 'é's
+/// let segments = split_at_char_boundaries("aéaéaéaé".as_bytes(), 4);
+/// assert_eq!(segments, ["aé", "aé", "aé", "aé"].map(str::as_bytes));
+/// assert_eq!(split_at_char_boundaries("𝄞".as_bytes(), 4), ["𝄞".as_bytes()]);
+/// ```
+pub fn split_at_char_boundaries(bytes: &[u8], n: usize) -> Vec<&[u8]> {
+    let n = normalize_threads(n);
+    // Continuation bytes are 0b10xxxxxx, anything else starts a character
+    let is_boundary = |i: usize| i >= bytes.len() || (bytes[i] as i8) >= -0x40;
+    let mut segments = Vec::with_capacity(n);
+    let mut start = 0;
+    for k in 1..=n {
+        let mut end = max(bytes.len() * k / n, start);
+        while !is_boundary(end) {
+            end += 1;
+        }
+        if end > start {
+            segments.push(&bytes[start..end]);
+        }
+        start = end;
+    }
+    segments
+}
+
+/// Counts the characters of one `text` on `n` threads, splitting it by bytes with
+/// [`split_at_char_boundaries`] rather than by lines, so a single huge line is shared too. Line
+/// breaks in `text` are counted like any other character.
+///
+/// ```
+/// use par_calc::{count_chars, count_chars_text_parallel};
+///
+/// let text = "añ日本🎉".repeat(100);
+/// assert_eq!(count_chars_text_parallel(&text, 7).unwrap(), count_chars(&[&text]));
+/// ```
+#[cfg(feature = "parallel")]
+pub fn count_chars_text_parallel(text: &str, n: usize) -> Result<CharCounter, CountError> {
+    let mut offset = 0;
+    let segments: Vec<&str> = split_at_char_boundaries(text.as_bytes(), n)
+        .into_iter()
+        .map(|segment| {
+            let piece = &text[offset..offset + segment.len()];
+            offset += segment.len();
+            piece
+        })
+        .collect();
+    let chunks: Vec<&[&str]> = segments.chunks(1).collect();
+    Ok(count_chunks(&chunks)?.0)
+}
+
+/// Adds the counts in `part` to `counter`.
+pub fn merge_counts(counter: &mut CharCounter, part: &CharCounter) {
+    *counter += part;
+}
+
+/// Character counts as a list sorted by character, one entry per character. For the small
+/// alphabets of skewed inputs this is cheaper to merge than a [`CharCounter`] map.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CompressedCounter(pub Vec<(char, u64)>);
+
+impl CompressedCounter {
+    /// Back to a map, as the counting functions return it.
+    pub fn into_counts(self) -> CharCounter {
+        self.0.into_iter().collect()
+    }
+}
+
+/// Turns `counts` into a [`CompressedCounter`].
+///
+/// ```
+/// use par_calc::{compress_counter, count_chars};
+///
+/// let compressed = compress_counter(&count_chars(&["GATTACA"]));
+/// assert_eq!(compressed.0, [('A', 3), ('C', 1), ('G', 1), ('T', 2)]);
+/// ```
+pub fn compress_counter(counts: &CharCounter) -> CompressedCounter {
+    let mut entries: Vec<(char, u64)> = counts.iter().map(|(&c, &n)| (c, n)).collect();
+    entries.sort_unstable();
+    CompressedCounter(entries)
+}
+
+/// Merges two [`CompressedCounter`]s in one pass over both, adding up the counts of the characters
+/// they share like [`merge_counts`] does.
+///
+/// ```
+/// use par_calc::{compress_counter, count_chars, merge_compressed};
+///
+/// let merged = merge_compressed(compress_counter(&count_chars(&["ACGT"])), compress_counter(&count_chars(&["AAN"])));
+/// assert_eq!(merged.0, [('A', 3), ('C', 1), ('G', 1), ('N', 1), ('T', 1)]);
+/// ```
+pub fn merge_compressed(a: CompressedCounter, b: CompressedCounter) -> CompressedCounter {
+    let mut merged = Vec::with_capacity(a.0.len() + b.0.len());
+    let (mut a, mut b) = (a.0.into_iter().peekable(), b.0.into_iter().peekable());
+    loop {
+        let next = match (a.peek(), b.peek()) {
+            (Some(&(c, n)), Some(&(d, m))) if c == d => {
+                a.next();
+                b.next();
+                (c, n.saturating_add(m))
+            }
+            (Some(&(c, _)), Some(&(d, _))) if c < d => a.next().unwrap(),
+            (_, Some(_)) => b.next().unwrap(),
+            (Some(_), None) => a.next().unwrap(),
+            (None, None) => return CompressedCounter(merged),
+        };
+        merged.push(next);
+    }
+}
+
+/// Why a parallel count failed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CountError {
+    /// The thread counting chunk `chunk_index` panicked, the first one if several did. The
+    /// other chunks' counts are discarded.
+    WorkerPanic {
+        chunk_index: usize,
+        /// The panic message of every thread that panicked, in chunk order
+        messages: Vec<String>,
+    },
+}
+
+impl fmt::Display for CountError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CountError::WorkerPanic { chunk_index, messages } if messages.len() > 1 => {
+                write!(f, "the thread counting chunk {chunk_index} and {} others panicked: {}", messages.len() - 1, messages.join("; "))
+            }
+            CountError::WorkerPanic { chunk_index, messages } => write!(f, "the thread counting chunk {chunk_index} panicked: {}", messages.join("; ")),
+        }
+    }
+}
+
+// The results of all threads, or the panics of those that failed, with `chunk_of` giving the
+// chunk a thread was counting. One failed chunk makes the whole count wrong, so the others are
+// dropped then.
+#[cfg(feature = "parallel")]
+fn joined<T>(parts: Vec<thread::Result<T>>, chunk_of: impl Fn(usize) -> usize) -> Result<Vec<T>, CountError> {
+    let mut panics: Vec<(usize, String)> = Vec::new();
+    let mut results = Vec::with_capacity(parts.len());
+    for (thread, part) in parts.into_iter().enumerate() {
+        match part {
+            Ok(result) => results.push(result),
+            Err(payload) => {
+                // panic! with a literal gives a &str, with format arguments a String
+                let message = match (payload.downcast_ref::<&str>(), payload.downcast_ref::<String>()) {
+                    (Some(message), _) => message.to_string(),
+                    (_, Some(message)) => message.clone(),
+                    _ => "unknown panic payload".to_string(),
+                };
+                panics.push((chunk_of(thread), message));
+            }
+        }
+    }
+    panics.sort_by_key(|&(chunk, _)| chunk);
+    match panics.first() {
+        Some(&(chunk_index, _)) => Err(CountError::WorkerPanic { chunk_index, messages: panics.into_iter().map(|(_, message)| message).collect() }),
+        None => Ok(results),
+    }
+}
+
+impl std::error::Error for CountError {}
+
+/// Counts characters on `n` threads, splitting the input by line count.
+#[cfg(feature = "parallel")]
+pub fn count_chars_parallel<S: AsRef<str> + Sync>(input: &[S], n: usize) -> Result<CharCounter, CountError> {
+    Ok(count_chunks(&split_input(input, n, Split::default()))?.0)
+}
+
+/// Like [`count_chars_from_reader`], reading `batch` lines at a time and counting each batch with
+/// [`count_chars_parallel`] on `n` threads. A panicking worker comes back as an error of kind
+/// `Other` wrapping the [`CountError`].
+///
+/// ```
+/// use std::io::Cursor;
+/// use par_calc::{count_chars, count_chars_parallel_from_reader};
+///
+/// let text = "line one\nline two\nthree\n".repeat(100);
+/// let counts = count_chars_parallel_from_reader(Cursor::new(text.as_bytes()), 4, 64).unwrap();
+/// assert_eq!(counts, count_chars(&text.lines().collect::<Vec<_>>()));
+/// ```
+#[cfg(feature = "parallel")]
+pub fn count_chars_parallel_from_reader<R: BufRead>(mut reader: R, n: usize, batch: usize) -> io::Result<CharCounter> {
+    let mut counter = CharCounter::default();
+    let mut lines = Vec::with_capacity(batch.max(1));
+    let mut line = Vec::new();
+    loop {
+        let text = next_line(&mut reader, &mut line)?;
+        let done = text.is_none();
+        lines.extend(text.map(str::to_string));
+        if lines.len() >= batch.max(1) || (done && !lines.is_empty()) {
+            counter += count_chars_parallel(&lines, n).map_err(io::Error::other)?;
+            lines.clear();
+        }
+        if done {
+            return Ok(counter);
+        }
+    }
+}
+
+/// Counts the lines of the file at `path` on Rayon's thread pool as they are read, without
+/// loading the whole file first. Lines are handed to the threads one at a time, so this is
+/// slower than [`count_chars_parallel`] on inputs that fit in memory.
+#[cfg(feature = "rayon")]
+pub fn count_chars_par_bridge(path: &str) -> io::Result<CharCounter> {
+    use rayon::iter::{ParallelBridge, ParallelIterator};
+    io::BufReader::new(File::open(path)?)
+        .lines()
+        .par_bridge()
+        .try_fold(CharCounter::default, |mut counter, line| {
+            count_into(&mut counter, &line?);
+            Ok(counter)
+        })
+        .try_reduce(CharCounter::default, |counter, part| Ok(counter + part))
+}
+
+/// Counts each chunk on its own thread, also returning how many characters each one counted.
+#[cfg(feature = "parallel")]
+pub fn count_chunks<S: AsRef<str> + Sync>(chunks: &[&[S]]) -> Result<(CharCounter, Vec<u64>), CountError> {
+    count_chunks_with(chunks, count_chars)
+}
+
+/// Like [`count_chunks`], but counting each chunk with `engine` instead of [`count_chars`].
+///
+/// ```
+/// use par_calc::{count_chars, count_chunks_with};
+///
+/// let lines = ["ab", "b", "c"];
+/// let chunks: Vec<&[&str]> = lines.chunks(2).collect();
+/// let (counts, work) = count_chunks_with(&chunks, |chunk| count_chars(&chunk[..1])).unwrap();
+/// assert_eq!((counts.len(), work), (3, vec![2, 1]));
+/// ```
+#[cfg(feature = "parallel")]
+pub fn count_chunks_with<S, F>(chunks: &[&[S]], engine: F) -> Result<(CharCounter, Vec<u64>), CountError>
+where
+    S: Sync,
+    F: Fn(&[S]) -> CharCounter + Sync,
+{
+    let (parts, work) = count_parts(chunks, engine)?;
+    Ok((parts.into_iter().sum(), work))
+}
+
+// The count of every chunk on its own thread, and how many characters each counted
+#[cfg(feature = "parallel")]
+fn count_parts<S, F>(chunks: &[&[S]], engine: F) -> Result<(Vec<CharCounter>, Vec<u64>), CountError>
+where
+    S: Sync,
+    F: Fn(&[S]) -> CharCounter + Sync,
+{
+    let engine = &engine;
+    let parts: Vec<_> = thread::scope(|s| {
+        let handles: Vec<_> = chunks
+            .iter()
+            .map(|&chunk| s.spawn(move || {
+                let counter = engine(chunk);
+                let total = counter.total();
+                (counter, total)
+            }))
+            .collect();
+        handles.into_iter().map(|handle| handle.join()).collect()
+    });
+    Ok(joined(parts, |chunk| chunk)?.into_iter().unzip())
+}
+
+/// Counts the input in chunks of `chunk_lines` lines on at most `n` threads, each thread taking
+/// the next chunk off a shared queue when done with the last, so that a thread that is slowed
+/// down, or got the long lines, doesn't hold up the whole count. Also returns how many characters
+/// each thread counted. A panic is reported with the chunk the thread was counting.
+///
+/// ```
+/// use par_calc::{count_chars, count_chunks_queued};
+///
+/// let lines = ["ab", "b", "c", "dd", "e"];
+/// let (counts, work) = count_chunks_queued(&lines, 2, 2).unwrap();
+/// assert_eq!(counts, count_chars(&lines));
+/// assert_eq!((work.len(), work.iter().sum::<u64>()), (2, 7));
+/// ```
+#[cfg(feature = "parallel")]
+pub fn count_chunks_queued<S: AsRef<str> + Sync>(input: &[S], n: usize, chunk_lines: usize) -> Result<(CharCounter, Vec<u64>), CountError> {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    let chunks: Vec<&[S]> = input.chunks(max(chunk_lines, 1)).collect();
+    let next = AtomicUsize::new(0);
+    let threads = normalize_threads(n).min(chunks.len());
+    // The chunk each thread took last, which is the one it was on if it panicked
+    let taken: Vec<AtomicUsize> = (0..threads).map(|_| AtomicUsize::new(0)).collect();
+    let worker = |taken: &AtomicUsize| {
+        let mut counter = CharCounter::default();
+        loop {
+            let index = next.fetch_add(1, Ordering::Relaxed);
+            let Some(chunk) = chunks.get(index) else { break };
+            taken.store(index, Ordering::Relaxed);
+            for line in *chunk {
+                count_into(&mut counter, line.as_ref());
+            }
+        }
+        let total = counter.total();
+        (counter, total)
+    };
+    let parts: Vec<_> = thread::scope(|s| {
+        let handles: Vec<_> = taken.iter().map(|taken| s.spawn(move || worker(taken))).collect();
+        handles.into_iter().map(|handle| handle.join()).collect()
+    });
+    let (parts, work): (Vec<CharCounter>, Vec<u64>) = joined(parts, |thread| taken[thread].load(Ordering::Relaxed))?.into_iter().unzip();
+    Ok((parts.into_iter().sum(), work))
+}
+
+/// Per-thread maps for [`count_chunks_pooled`] to count into, kept for the next count once they
+/// are handed back instead of being freed. Maps come back cleared but keep their capacity, so
+/// repeated counts of similar input stop allocating after the first one. Needs the `pool`
+/// feature.
+///
+/// ```
+/// use par_calc::{count_chars, count_chars_parallel_pooled, CounterPool};
+///
+/// let pool = CounterPool::new();
+/// let lines = ["abc", "cab", "ccc"];
+/// for _ in 0..3 {
+///     let counts = count_chars_parallel_pooled(&lines, 2, &pool).unwrap();
+///     assert_eq!(counts, count_chars(&lines));
+///     pool.give(counts);
+/// }
+/// assert_eq!(pool.len(), 2);
+/// ```
+#[cfg(feature = "pool")]
+#[derive(Debug, Default)]
+pub struct CounterPool {
+    free: std::sync::Mutex<Vec<CharCounter>>,
+}
+
+#[cfg(feature = "pool")]
+impl CounterPool {
+    pub fn new() -> CounterPool {
+        CounterPool::default()
+    }
+
+    /// An empty map, one that was given back if there is any.
+    pub fn take(&self) -> CharCounter {
+        self.free.lock().unwrap_or_else(|e| e.into_inner()).pop().unwrap_or_default()
+    }
+
+    /// Clears `counter` and keeps it for a later [`CounterPool::take`].
+    pub fn give(&self, mut counter: CharCounter) {
+        counter.clear();
+        self.free.lock().unwrap_or_else(|e| e.into_inner()).push(counter);
+    }
+
+    /// Number of maps waiting to be taken.
+    pub fn len(&self) -> usize {
+        self.free.lock().unwrap_or_else(|e| e.into_inner()).len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Like [`count_chunks`], counting into maps taken from `pool`. All but the largest of them go
+/// back to the pool after the merge, the caller can [`CounterPool::give`] back the result too.
+#[cfg(feature = "pool")]
+pub fn count_chunks_pooled<S: AsRef<str> + Sync>(chunks: &[&[S]], pool: &CounterPool) -> Result<(CharCounter, Vec<u64>), CountError> {
+    let (mut parts, work) = count_parts(chunks, |chunk| {
+        let mut counter = pool.take();
+        for line in chunk {
+            count_into(&mut counter, line.as_ref());
+        }
+        counter
+    })?;
+    let largest = (0..parts.len()).max_by_key(|&i| parts[i].len());
+    let mut counter = largest.map(|i| parts.swap_remove(i)).unwrap_or_else(|| pool.take());
+    for part in parts {
+        counter += &part;
+        pool.give(part);
+    }
+    Ok((counter, work))
+}
+
+/// Like [`count_chars_parallel`], counting into maps taken from `pool`.
+#[cfg(feature = "pool")]
+pub fn count_chars_parallel_pooled<S: AsRef<str> + Sync>(input: &[S], n: usize, pool: &CounterPool) -> Result<CharCounter, CountError> {
+    Ok(count_chunks_pooled(&split_input(input, n, Split::default()), pool)?.0)
+}
+
+/// When the threads of [`count_chars_parallel_instrumented`] finished, measured from just before
+/// they were spawned.
+#[cfg(feature = "bench")]
+#[derive(Clone, Copy, Debug)]
+pub struct InstrumentationReport {
+    /// Until the first thread had its result, roughly the cost of starting the threads
+    pub spawn_latency: Duration,
+    /// Until the last thread had its result
+    pub completion_latency: Duration,
+    /// Until the results were merged
+    pub total: Duration,
+}
+
+/// Like [`count_chars_parallel`], also timing when the per-thread results arrive.
+///
+/// ```
+/// use par_calc::{count_chars, count_chars_parallel_instrumented};
+///
+/// let (counts, report) = count_chars_parallel_instrumented(&["ab", "bc", "c"], 3).unwrap();
+/// assert_eq!(counts, count_chars(&["ab", "bc", "c"]));
+/// assert!(report.spawn_latency <= report.completion_latency && report.completion_latency <= report.total);
+/// ```
+#[cfg(feature = "bench")]
+pub fn count_chars_parallel_instrumented<S: AsRef<str> + Sync>(input: &[S], n: usize) -> Result<(CharCounter, InstrumentationReport), CountError> {
+    let chunks = split_input(input, n, Split::default());
+    let finished = std::sync::Mutex::new(Vec::with_capacity(chunks.len()));
+    let start = Instant::now();
+    let (counts, _) = count_chunks_with(&chunks, |chunk| {
+        let counts = count_chars(chunk);
+        finished.lock().unwrap().push(start.elapsed());
+        counts
+    })?;
+    let total = start.elapsed();
+    let finished = finished.into_inner().unwrap();
+    let report = InstrumentationReport {
+        spawn_latency: finished.iter().copied().min().unwrap_or(total),
+        completion_latency: finished.iter().copied().max().unwrap_or(total),
+        total,
+    };
+    Ok((counts, report))
+}
+
+/// Counts characters on `n` threads that all update one shared concurrent map, instead of merging
+/// per-thread maps at the end like [`count_chars_parallel`].
+///
+/// ```
+/// use par_calc::{count_chars, count_chars_dashmap};
+///
+/// let lines = ["abc", "cab", "ccc"];
+/// assert_eq!(count_chars_dashmap(&lines, 2), count_chars(&lines));
+/// ```
+#[cfg(feature = "dashmap")]
+pub fn count_chars_dashmap<S: AsRef<str> + Sync>(input: &[S], n: usize) -> CharCounter {
+    use std::sync::atomic::{AtomicU64, Ordering};
+    let counter = dashmap::DashMap::<char, AtomicU64, BuildCharHasher>::default();
+    thread::scope(|s| {
+        for chunk in split_input(input, n, Split::default()) {
+            let counter = &counter;
+            s.spawn(move || {
+                for text in chunk {
+                    for c in text.as_ref().chars() {
+                        // Shared read lock on the shard in the common case, the write lock is
+                        // only needed the first time a character is seen
+                        match counter.get(&c) {
+                            Some(count) => count.fetch_add(1, Ordering::Relaxed),
+                            None => counter.entry(c).or_default().fetch_add(1, Ordering::Relaxed),
+                        };
+                    }
+                }
+            });
+        }
+    });
+    counter.into_iter().map(|(c, count)| (c, count.into_inner())).collect()
+}
+
+/// A way of counting the characters of `input` on up to `threads` threads, as
+/// the engines of [`Engine`] do.
+///
+/// ```
+/// use par_calc::{count_chars, CountEngine, Engine};
+///
+/// let lines = ["abc", "cab"];
+/// for engine in Engine::available() {
+///     assert_eq!(engine.count(&lines, 2).unwrap(), count_chars(&lines));
+/// }
+/// ```
+pub trait CountEngine {
+    /// Counts `input`, failing only if a thread of the count panicked.
+    fn count(&self, input: &[&str], threads: usize) -> Result<CharCounter, CountError>;
+}
+
+/// The counting engines of this crate, both for choosing one and for timing them against each
+/// other with [`benchmark_engine`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Engine {
+    /// [`count_chars`] on the calling thread
+    Serial,
+    /// [`count_chars_parallel`], per-thread maps merged at the end
+    #[cfg(feature = "parallel")]
+    Parallel,
+    /// [`count_chars_dashmap`], one map shared by all threads
+    #[cfg(feature = "dashmap")]
+    Dashmap,
+}
+
+impl Engine {
+    /// The engines this build has, the optional ones depending on features.
+    ///
+    /// ```
+    /// use par_calc::Engine;
+    ///
+    /// assert_eq!(Engine::available()[0], Engine::Serial);
+    /// assert!(Engine::available().into_iter().all(|engine| Engine::from_name(engine.name()) == Some(engine)));
+    /// assert_eq!(Engine::from_name("parallel").is_some(), cfg!(feature = "parallel"));
+    /// ```
+    pub fn available() -> Vec<Engine> {
+        vec![
+            Engine::Serial,
+            #[cfg(feature = "parallel")]
+            Engine::Parallel,
+            #[cfg(feature = "dashmap")]
+            Engine::Dashmap,
+        ]
+    }
+
+    /// Short lowercase name, as the command line tool takes and prints it.
+    pub fn name(self) -> &'static str {
+        match self {
+            Engine::Serial => "serial",
+            #[cfg(feature = "parallel")]
+            Engine::Parallel => "parallel",
+            #[cfg(feature = "dashmap")]
+            Engine::Dashmap => "dashmap",
+        }
+    }
+
+    /// The available engine called `name`.
+    pub fn from_name(name: &str) -> Option<Engine> {
+        Engine::available().into_iter().find(|engine| engine.name() == name)
+    }
+}
+
+impl CountEngine for Engine {
+    // Only the serial engine is left without the parallel feature, and it has one thread
+    #[cfg_attr(not(feature = "parallel"), allow(unused_variables))]
+    fn count(&self, input: &[&str], threads: usize) -> Result<CharCounter, CountError> {
+        match self {
+            Engine::Serial => Ok(count_chars(input)),
+            #[cfg(feature = "parallel")]
+            Engine::Parallel => count_chars_parallel(input, threads),
+            #[cfg(feature = "dashmap")]
+            Engine::Dashmap => Ok(count_chars_dashmap(input, threads)),
+        }
+    }
+}
+
+/// Adds up character counts per Unicode script, keyed by the script's full name such as "Latin",
+/// "Cyrillic" or "Han". Spaces, digits and punctuation shared by many scripts are "Common".
+/// Needs the `scripts` feature.
+///
+/// ```
+/// use par_calc::{count_chars, count_scripts};
+///
+/// let scripts = count_scripts(&count_chars(&["hi мир 世界"]));
+/// assert_eq!((scripts["Latin"], scripts["Cyrillic"], scripts["Han"], scripts["Common"]), (2, 3, 2, 2));
+/// ```
+#[cfg(feature = "scripts")]
+pub fn count_scripts(counts: &CharCounter) -> HashMap<&'static str, u64> {
+    let mut scripts = HashMap::new();
+    for (&c, &n) in counts {
+        let script: &mut u64 = scripts.entry(unicode_script::UnicodeScript::script(&c).full_name()).or_default();
+        *script = script.saturating_add(n);
+    }
+    scripts
+}
+
+/// Adds Laplace noise of scale `1 / epsilon` to every count, rounding and clamping at zero, and
+/// drops the characters that end up at zero. Needs the `privacy` feature.
+///
+/// Adding or removing one character of the input changes one count by one, so the sensitivity is 1
+/// and the noisy counts are `epsilon`-differentially private with respect to any single
+/// character: their distribution changes by at most a factor of `e^epsilon` with or without it.
+/// Rounding, clamping and dropping are post-processing and keep that guarantee. The set of
+/// characters the counts start from is not noised though, so whether a character occurs at all
+/// is only hidden to the extent that its noisy count rounds to zero. Smaller `epsilon` means more
+/// noise and more privacy, it has to be positive.
+///
+/// ```
+/// use rand::SeedableRng;
+/// use par_calc::{add_laplace_noise, count_chars};
+///
+/// let mut counts = count_chars(&["a".repeat(100_000)]);
+/// add_laplace_noise(&mut counts, 1.0, &mut rand::rngs::StdRng::seed_from_u64(7));
+/// assert!(counts[&'a'].abs_diff(100_000) < 50);
+/// ```
+#[cfg(feature = "privacy")]
+pub fn add_laplace_noise<R: rand::Rng>(counts: &mut CharCounter, epsilon: f64, rng: &mut R) {
+    let scale = 1.0 / epsilon;
+    // In code point order, so that a seeded `rng` gives the same noise whatever the map's order
+    let mut chars: Vec<char> = counts.keys().copied().collect();
+    chars.sort_unstable();
+    for c in chars {
+        // Inverse of the Laplace CDF applied to a uniform sample
+        let u: f64 = rng.gen_range(-0.5..0.5);
+        let noise = -scale * u.signum() * (1.0 - 2.0 * u.abs()).ln();
+        let noisy = (counts[&c] as f64 + noise).round().max(0.0) as u64;
+        if noisy == 0 {
+            counts.remove(&c);
+        } else {
+            counts.insert(c, noisy);
+        }
+    }
+}
+
+/// A granularity at which text can be counted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Unit {
+    /// Extended grapheme clusters, needs the `graphemes` feature
+    #[cfg(feature = "graphemes")]
+    Grapheme,
+    /// Unicode scalar values
+    Char,
+    /// UTF-8 bytes
+    Byte,
+}
+
+/// Number of lines each unordered pair of distinct characters appears together on, keyed with
+/// the smaller character first.
+pub type PairCounts = HashMap<(char, char), u64, BuildCharHasher>;
+
+/// Counts, per line, every pair of distinct characters the line contains.
+///
+/// ```
+/// use par_calc::count_cooccurrences;
+///
+/// let pairs = count_cooccurrences(&["abca", "ba", "c"]);
+/// assert_eq!(pairs[&('a', 'b')], 2);
+/// assert_eq!(pairs[&('b', 'c')], 1);
+/// assert_eq!(pairs.len(), 3);
+/// ```
+pub fn count_cooccurrences<S: AsRef<str>>(input: &[S]) -> PairCounts {
+    let mut pairs = PairCounts::default();
+    for line in input {
+        let mut chars: Vec<char> = line.as_ref().chars().collect();
+        chars.sort_unstable();
+        chars.dedup();
+        for (i, &a) in chars.iter().enumerate() {
+            for &b in &chars[i + 1..] {
+                *pairs.entry((a, b)).or_default() += 1;
+            }
+        }
+    }
+    pairs
+}
+
+/// The `n` lines with the most occurrences of `target`, as (line index, occurrences) pairs, most
+/// first and earlier lines first among equals. Lines without `target` are left out.
+///
+/// ```
+/// use par_calc::top_contributing_lines;
+///
+/// let lines = ["aab", "b", "aaa", "ab"];
+/// assert_eq!(top_contributing_lines(&lines, 'a', 2), [(2, 3), (0, 2)]);
+/// assert_eq!(top_contributing_lines(&lines, 'b', 5), [(0, 1), (1, 1), (3, 1)]);
+/// ```
+pub fn top_contributing_lines<S: AsRef<str>>(lines: &[S], target: char, n: usize) -> Vec<(usize, usize)> {
+    top_lines_from(lines, 0, target, n)
+}
+
+/// Like [`top_contributing_lines`], scanning the lines on `threads` threads.
+///
+/// ```
+/// use par_calc::{top_contributing_lines, top_contributing_lines_parallel};
+///
+/// let lines = ["aab", "b", "aaa", "ab"];
+/// assert_eq!(top_contributing_lines_parallel(&lines, 'a', 2, 3).unwrap(), top_contributing_lines(&lines, 'a', 2));
+/// ```
+#[cfg(feature = "parallel")]
+pub fn top_contributing_lines_parallel<S: AsRef<str> + Sync>(lines: &[S], target: char, n: usize, threads: usize) -> Result<Vec<(usize, usize)>, CountError> {
+    let parts: Vec<_> = thread::scope(|s| {
+        let mut offset = 0;
+        let mut handles = Vec::new();
+        for chunk in split_input(lines, threads, Split::default()) {
+            handles.push(s.spawn(move || top_lines_from(chunk, offset, target, n)));
+            offset += chunk.len();
+        }
+        handles.into_iter().map(|handle| handle.join()).collect()
+    });
+    // The top n of every chunk together hold the top n of the whole input
+    let top: Vec<(usize, usize)> = joined(parts, |chunk| chunk)?.into_iter().flatten().collect();
+    Ok(top_lines(top, n))
+}
+
+fn top_lines_from<S: AsRef<str>>(lines: &[S], offset: usize, target: char, n: usize) -> Vec<(usize, usize)> {
+    let top: Vec<(usize, usize)> = lines
+        .iter()
+        .enumerate()
+        .map(|(i, line)| (offset + i, line.as_ref().matches(target).count()))
+        .filter(|&(_, count)| count > 0)
+        .collect();
+    top_lines(top, n)
+}
+
+fn top_lines(lines: Vec<(usize, usize)>, n: usize) -> Vec<(usize, usize)> {
+    smallest_by(lines, n, |a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)))
+}
+
+/// Distributions of the requested units, `None` for the ones that weren't asked for.
+#[derive(Clone, Debug, Default)]
+pub struct UnitCounts {
+    #[cfg(feature = "graphemes")]
+    pub graphemes: Option<HashMap<String, u64>>,
+    pub chars: Option<CharCounter>,
+    pub bytes: Option<HashMap<u8, u64>>,
+}
+
+/// Counts all requested units in a single pass over the input.
+///
+/// ```
+/// use par_calc::{count_units, Unit};
+///
+/// let counts = count_units(&["née"], &[Unit::Char, Unit::Byte]);
+/// assert_eq!(counts.chars.unwrap()[&'é'], 1);
+/// assert_eq!(counts.bytes.unwrap()[&0xC3], 1);
+/// ```
+pub fn count_units<S: AsRef<str>>(input: &[S], units: &[Unit]) -> UnitCounts {
+    let mut counts = UnitCounts::default();
+    for unit in units {
+        match unit {
+            #[cfg(feature = "graphemes")]
+            Unit::Grapheme => counts.graphemes = Some(HashMap::new()),
+            Unit::Char => counts.chars = Some(CharCounter::default()),
+            Unit::Byte => counts.bytes = Some(HashMap::new()),
+        }
+    }
+    for text in input {
+        let text = text.as_ref();
+        #[cfg(feature = "graphemes")]
+        if let Some(graphemes) = &mut counts.graphemes {
+            for g in unicode_segmentation::UnicodeSegmentation::graphemes(text, true) {
+                let count = graphemes.entry(g.to_string()).or_default();
+                *count = count.saturating_add(1);
+            }
+        }
+        if let Some(chars) = &mut counts.chars {
+            for c in text.chars() {
+                let count = chars.entry(c).or_default();
+                *count = count.saturating_add(1);
+            }
+        }
+        if let Some(bytes) = &mut counts.bytes {
+            for &b in text.as_bytes() {
+                let count = bytes.entry(b).or_default();
+                *count = count.saturating_add(1);
+            }
+        }
+    }
+    counts
+}
+
+/// Grapheme counts from [`count_graphemes_bounded`].
+#[cfg(feature = "graphemes")]
+#[derive(Clone, Debug, Default)]
+pub struct BoundedCounts {
+    pub graphemes: HashMap<String, u64>,
+    /// Occurrences of the graphemes that were spilled to disk and never made it back into the map
+    pub dropped: u64,
+}
+
+// Spilled entries as the count, the length in bytes and the grapheme, since a grapheme can be "\r\n"
+#[cfg(feature = "graphemes")]
+fn spill(file: &mut impl io::Write, graphemes: &mut HashMap<String, u64>, max_keys: usize) -> io::Result<()> {
+    let mut entries: Vec<(String, u64)> = graphemes.drain().collect();
+    entries.sort_unstable_by_key(|&(_, n)| Reverse(n));
+    for (g, n) in entries.split_off(max_keys / 2) {
+        file.write_all(&n.to_le_bytes())?;
+        file.write_all(&(g.len() as u64).to_le_bytes())?;
+        file.write_all(g.as_bytes())?;
+    }
+    graphemes.extend(entries);
+    Ok(())
+}
+
+// The spill file, removed however count_graphemes_bounded returns
+#[cfg(feature = "graphemes")]
+struct SpillFile(std::path::PathBuf);
+
+#[cfg(feature = "graphemes")]
+impl Drop for SpillFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+/// Counts graphemes keeping at most `max_keys` of them in memory. Whenever the map grows past
+/// that, its less frequent half is appended to a temporary file, and at the end the spilled counts
+/// are added back to the graphemes still in the map. A grapheme that keeps coming back is thus
+/// counted exactly, only the ones that got spilled and never reappeared are lost, in `dropped`.
+///
+/// ```
+/// use par_calc::count_graphemes_bounded;
+///
+/// let counts = count_graphemes_bounded(&["aaaa", "bc", "a"], 2).unwrap();
+/// assert_eq!(counts.graphemes["a"], 5);
+/// // "b" and "c" were spilled to make room and never seen again
+/// assert_eq!(counts.dropped, 2);
+/// ```
+#[cfg(feature = "graphemes")]
+pub fn count_graphemes_bounded<S: AsRef<str>>(input: &[S], max_keys: usize) -> io::Result<BoundedCounts> {
+    use std::io::{Read, Seek};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    static SPILLS: AtomicUsize = AtomicUsize::new(0);
+
+    let max_keys = max_keys.max(2);
+    let path = std::env::temp_dir().join(format!("par_calc-{}-{}.spill", std::process::id(), SPILLS.fetch_add(1, Ordering::Relaxed)));
+    let file = File::options().read(true).write(true).create_new(true).open(&path)?;
+    let _spill_file = SpillFile(path);
+    let mut writer = io::BufWriter::new(file);
+    let mut counts = BoundedCounts::default();
+    for text in input {
+        for g in unicode_segmentation::UnicodeSegmentation::graphemes(text.as_ref(), true) {
+            *counts.graphemes.entry(g.to_string()).or_default() += 1;
+            if counts.graphemes.len() > max_keys {
+                spill(&mut writer, &mut counts.graphemes, max_keys)?;
+            }
+        }
+    }
+
+    let mut file = writer.into_inner().map_err(io::IntoInnerError::into_error)?;
+    file.rewind()?;
+    let mut reader = io::BufReader::new(file);
+    let mut word = [0; 8];
+    loop {
+        match reader.read_exact(&mut word) {
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+            result => result?,
+        }
+        let n = u64::from_le_bytes(word);
+        reader.read_exact(&mut word)?;
+        let mut g = vec![0; u64::from_le_bytes(word) as usize];
+        reader.read_exact(&mut g)?;
+        match std::str::from_utf8(&g).ok().and_then(|g| counts.graphemes.get_mut(g)) {
+            Some(count) => *count = count.saturating_add(n),
+            None => counts.dropped = counts.dropped.saturating_add(n),
+        }
+    }
+    Ok(counts)
+}
+
+/// Order of a frequency listing.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SortKey {
+    /// Most frequent first
+    #[default]
+    CountDesc,
+    /// Least frequent first
+    CountAsc,
+    /// By the character, in code point order like [`SortKey::CodepointAsc`]
+    CharAsc,
+    CharDesc,
+    /// By Unicode scalar value
+    CodepointAsc,
+    CodepointDesc,
+    /// Alphabetical, upper and lower case of a letter next to each other and accented letters
+    /// next to their base letter, by canonical decomposition. Needs the `collation` feature.
+    #[cfg(feature = "collation")]
+    CollatedAsc,
+    #[cfg(feature = "collation")]
+    CollatedDesc,
+}
+
+/// Sorts a frequency listing by `key`. Ties in count are broken by code point.
+///
+/// ```
+/// use par_calc::{sort_freq, SortKey};
+///
+/// let mut freq = vec![('b', 1), ('é', 1), ('B', 2), ('a', 2)];
+/// sort_freq(&mut freq, SortKey::CharAsc);
+/// assert_eq!(freq, [('B', 2), ('a', 2), ('b', 1), ('é', 1)]);
+/// # #[cfg(feature = "collation")] {
+/// sort_freq(&mut freq, SortKey::CollatedAsc);
+/// assert_eq!(freq, [('a', 2), ('B', 2), ('b', 1), ('é', 1)]);
+/// # }
+/// ```
+pub fn sort_freq(freq: &mut [(char, u64)], key: SortKey) {
+    match key {
+        SortKey::CountDesc => freq.sort_unstable_by_key(|&(c, n)| (Reverse(n), c)),
+        SortKey::CountAsc => freq.sort_unstable_by_key(|&(c, n)| (n, c)),
+        SortKey::CharAsc | SortKey::CodepointAsc => freq.sort_unstable_by_key(|&(c, _)| c),
+        SortKey::CharDesc | SortKey::CodepointDesc => freq.sort_unstable_by_key(|&(c, _)| Reverse(c)),
+        #[cfg(feature = "collation")]
+        SortKey::CollatedAsc => freq.sort_by_cached_key(|&(c, _)| collation_key(c)),
+        #[cfg(feature = "collation")]
+        SortKey::CollatedDesc => freq.sort_by_cached_key(|&(c, _)| Reverse(collation_key(c))),
+    }
+}
+
+// Lowercase without accents first, so that e, E, é and É end up next to each other
+#[cfg(feature = "collation")]
+fn collation_key(c: char) -> (String, String, char) {
+    use unicode_normalization::{char::is_combining_mark, UnicodeNormalization};
+    let lower: String = c.to_lowercase().collect();
+    let base = lower.nfd().filter(|&c| !is_combining_mark(c)).collect();
+    (base, lower, c)
+}
+
+/// Like [`sort_freq`], but ties in count are broken by position in `order`, e.g. the encounter
+/// order from [`count_chars_ordered`]. Characters missing from `order` go last.
+///
+/// ```
+/// use par_calc::{sort_freq_by_order, SortKey};
+///
+/// let mut freq = vec![('a', 1), ('c', 2), ('b', 1)];
+/// sort_freq_by_order(&mut freq, SortKey::CountDesc, &['c', 'b', 'a']);
+/// assert_eq!(freq, [('c', 2), ('b', 1), ('a', 1)]);
+/// ```
+pub fn sort_freq_by_order(freq: &mut [(char, u64)], key: SortKey, order: &[char]) {
+    let position: HashMap<char, usize> = order.iter().enumerate().map(|(i, &c)| (c, i)).collect();
+    let position = |c: char| (position.get(&c).copied().unwrap_or(usize::MAX), c);
+    match key {
+        SortKey::CountDesc => freq.sort_unstable_by_key(|&(c, n)| (Reverse(n), position(c))),
+        SortKey::CountAsc => freq.sort_unstable_by_key(|&(c, n)| (n, position(c))),
+        _ => sort_freq(freq, key),
+    }
+}
+
+/// The stats listing of `counter`: its first `n` characters by `key`, ties in count broken by
+/// position in `order` if one is given and by code point otherwise. With `top_percent` only the
+/// most frequent characters making up at least that share of all occurrences are listed, in `key`
+/// order. Every output format lists the characters this gives, in this order.
+///
+/// ```
+/// use par_calc::{count_chars, list_freq, SortKey};
+///
+/// let counts = count_chars(&["cbaa"]);
+/// assert_eq!(list_freq(&counts, SortKey::CountDesc, None, None, 2), [('a', 2), ('b', 1)]);
+/// assert_eq!(list_freq(&counts, SortKey::CountDesc, Some(&['c', 'b', 'a']), None, 2), [('a', 2), ('c', 1)]);
+/// assert_eq!(list_freq(&counts, SortKey::CharDesc, None, Some(70.0), usize::MAX), [('b', 1), ('a', 2)]);
+/// ```
+pub fn list_freq(counter: &CharCounter, key: SortKey, order: Option<&[char]>, top_percent: Option<f64>, n: usize) -> Vec<(char, u64)> {
+    // The usual listing, the most frequent first, only needs the characters it shows sorted
+    if top_percent.is_none() && order.is_none() && key == SortKey::CountDesc {
+        return counter.top_n(n);
+    }
+    let mut freq = counter.top_n(usize::MAX);
+    // The share picks the characters by count whatever order they are listed in
+    if let Some(percent) = top_percent {
+        freq.truncate(share_cutoff(&freq, percent));
+    }
+    match order {
+        Some(order) => sort_freq_by_order(&mut freq, key, order),
+        None => sort_freq(&mut freq, key),
+    }
+    freq.truncate(n);
+    freq
+}
+
+// How many of the most frequent characters of `freq`, sorted by descending count, it takes for
+// their counts to add up to `percent` of all of them
+fn share_cutoff(freq: &[(char, u64)], percent: f64) -> usize {
+    let total: u64 = freq.iter().map(|&(_, n)| n).sum();
+    let wanted = total as f64 * percent / 100.0;
+    let mut sum = 0;
+    for (i, &(_, n)) in freq.iter().enumerate() {
+        sum += n;
+        if sum as f64 >= wanted {
+            return i + 1;
+        }
+    }
+    freq.len()
+}
+
+/// The shares of all occurrences [`concentration_report`] finds the covering characters for.
+pub const CONCENTRATION_LEVELS: [u32; 4] = [50, 80, 95, 99];
+
+/// How concentrated the counts are on the most frequent characters.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConcentrationReport {
+    /// `(percent, chars)` for each of [`CONCENTRATION_LEVELS`]: the `chars` most frequent
+    /// characters together make up at least `percent`% of all occurrences, and fewer would not.
+    pub levels: Vec<(u32, usize)>,
+}
+
+/// Finds how few of the most frequent characters cover each of [`CONCENTRATION_LEVELS`].
+///
+/// ```
+/// use par_calc::{concentration_report, count_chars};
+///
+/// let report = concentration_report(&count_chars(&["aaaaaaaabc"]));
+/// assert_eq!(report.levels, [(50, 1), (80, 1), (95, 3), (99, 3)]);
+/// ```
+pub fn concentration_report(counts: &CharCounter) -> ConcentrationReport {
+    let mut freq: Vec<(char, u64)> = counts.iter().map(|(&c, &n)| (c, n)).collect();
+    sort_freq(&mut freq, SortKey::CountDesc);
+    let total = counts.total() as u128;
+    let levels = CONCENTRATION_LEVELS
+        .iter()
+        .map(|&percent| {
+            // Integer arithmetic, so that exactly 50% of the occurrences counts as covering 50%
+            let (mut covered, mut chars) = (0u128, 0);
+            while covered * 100 < total * percent as u128 {
+                covered += freq[chars].1 as u128;
+                chars += 1;
+            }
+            (percent, chars)
+        })
+        .collect();
+    ConcentrationReport { levels }
+}
+
+/// Shannon entropy of the character distribution, in bits per character.
+///
+/// ```
+/// use par_calc::{count_chars, entropy};
+///
+/// assert_eq!(entropy(&count_chars(&["aaaa"])), 0.0);
+/// assert_eq!(entropy(&count_chars(&["abcd"])), 2.0);
+/// ```
+pub fn entropy(counts: &CharCounter) -> f64 {
+    let total = counts.total() as f64;
+    counts.values().filter(|&&n| n > 0).map(|&n| n as f64 / total).map(|p| -p * p.log2()).sum()
+}
+
+/// Cosine similarity of two sets of counts seen as vectors indexed by character: 1 for the same
+/// proportions, 0 when they have no character in common or either is empty.
+///
+/// ```
+/// use par_calc::{cosine_similarity, count_chars};
+///
+/// assert!((cosine_similarity(&count_chars(&["ab"]), &count_chars(&["aabb"])) - 1.0).abs() < 1e-12);
+/// assert_eq!(cosine_similarity(&count_chars(&["ab"]), &count_chars(&["cd"])), 0.0);
+/// ```
+pub fn cosine_similarity(a: &CharCounter, b: &CharCounter) -> f64 {
+    let norm = |counts: &CharCounter| counts.values().map(|&n| (n as f64).powi(2)).sum::<f64>().sqrt();
+    // A fold from 0.0 because an empty sum of floats is -0.0
+    let dot = a.iter().filter_map(|(c, &n)| b.get(c).map(|&m| n as f64 * m as f64)).fold(0.0, |dot, x| dot + x);
+    let norms = norm(a) * norm(b);
+    if norms == 0.0 { 0.0 } else { (dot / norms).min(1.0) }
+}
+
+/// Distribution of the durations of the reruns of one count.
+#[cfg(feature = "bench")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LatencyStats {
+    /// Average duration
+    pub mean: Duration,
+    /// Median
+    pub p50: Duration,
+    /// 90th percentile, 90% of the counts took at most this long
+    pub p90: Duration,
+    /// 95th percentile
+    pub p95: Duration,
+    /// 99th percentile
+    pub p99: Duration,
+    /// Fastest count
+    pub min: Duration,
+    /// Slowest count
+    pub max: Duration,
+}
+
+#[cfg(feature = "bench")]
+impl LatencyStats {
+    /// Computes the stats of at least one duration, sorting `times` in place. Percentiles are
+    /// nearest-rank: the smallest duration at least that share of `times` is no longer than.
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use par_calc::LatencyStats;
+    ///
+    /// let mut times: Vec<Duration> = (1..=10).rev().map(Duration::from_millis).collect();
+    /// let stats = LatencyStats::from_times(&mut times);
+    /// assert_eq!(stats.p50, Duration::from_millis(5));
+    /// assert_eq!(stats.p90, Duration::from_millis(9));
+    /// assert_eq!(stats.p95, Duration::from_millis(10));
+    /// assert_eq!(stats.p99, Duration::from_millis(10));
+    /// assert_eq!(stats.mean, Duration::from_micros(5500));
+    /// ```
+    pub fn from_times(times: &mut [Duration]) -> LatencyStats {
+        times.sort_unstable();
+        let percentile = |p: usize| times[(times.len() * p).div_ceil(100).max(1) - 1];
+        LatencyStats {
+            mean: times.iter().sum::<Duration>() / times.len() as u32,
+            p50: percentile(50),
+            p90: percentile(90),
+            p95: percentile(95),
+            p99: percentile(99),
+            min: times[0],
+            max: times[times.len() - 1],
+        }
+    }
+}
+
+/// Timing of one parallelism level, as measured by [`benchmark`].
+#[cfg(feature = "bench")]
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BenchResult {
+    /// Number of threads the input was split between
+    pub threads: usize,
+    /// Number of times the count was repeated
+    pub reruns: u32,
+    /// Average, percentiles and extremes of the count durations
+    pub latency: LatencyStats,
+    /// All reruns together, for a mean that isn't truncated to whole nanoseconds
+    pub total: Duration,
+    /// Standard deviation of the count durations
+    pub stddev: Duration,
+    /// Characters counted by each thread in the last rerun
+    pub work: Vec<u64>,
+}
+
+#[cfg(feature = "bench")]
+impl BenchResult {
+    /// Mean duration of one count in nanoseconds, with the fraction [`LatencyStats::mean`] drops.
+    pub fn mean_nanos(&self) -> f64 {
+        self.total.as_nanos() as f64 / self.reruns as f64
+    }
+
+    /// Characters counted per second, None if the counts took no measurable time.
+    pub fn chars_per_sec(&self) -> Option<f64> {
+        let chars: u64 = self.work.iter().sum();
+        let mean = self.mean_nanos();
+        (mean > 0.0).then(|| chars as f64 * 1e9 / mean)
+    }
+}
+
+/// Counts the input `reruns` times on `n` threads and reports the average time. Only the timings
+/// are kept, the counts are for [`count_chars_parallel`] to produce.
+///
+/// ```
+/// use par_calc::{benchmark, Split};
+///
+/// let result = benchmark(&["abc", "cd"], 2, 3, Split::default()).unwrap();
+/// assert_eq!(result.threads, 2);
+/// assert_eq!(result.work, vec![3, 2]);
+/// ```
+#[cfg(feature = "bench")]
+pub fn benchmark<S: AsRef<str> + Sync>(input: &[S], n: usize, reruns: u32, split: Split) -> Result<BenchResult, CountError> {
+    benchmark_interruptible(input, n, reruns, split, &AtomicBool::new(false))
+}
+
+/// Like [`benchmark`], but stops after the rerun during which `stop` got set, with `reruns` in
+/// the result saying how many were done then. There is always at least one.
+///
+/// ```
+/// use std::sync::atomic::AtomicBool;
+/// use par_calc::{benchmark_interruptible, Split};
+///
+/// let result = benchmark_interruptible(&["abc"], 1, 100, Split::default(), &AtomicBool::new(true)).unwrap();
+/// assert_eq!(result.reruns, 1);
+/// ```
+#[cfg(feature = "bench")]
+pub fn benchmark_interruptible<S: AsRef<str> + Sync>(input: &[S], n: usize, reruns: u32, split: Split, stop: &AtomicBool) -> Result<BenchResult, CountError> {
+    match split.chunk_size.lines_for(input.len(), n) {
+        Some(chunk_lines) => time_reruns(n, reruns, stop, || Ok(std::hint::black_box(count_chunks_queued(input, n, chunk_lines)?).1)),
+        None => {
+            // With the pool feature every rerun counts into the maps of the one before
+            #[cfg(feature = "pool")]
+            let pool = CounterPool::new();
+            #[cfg(feature = "pool")]
+            let count = || {
+                let (counts, work) = count_chunks_pooled(&split_input(input, n, split), &pool)?;
+                pool.give(std::hint::black_box(counts));
+                Ok(work)
+            };
+            #[cfg(not(feature = "pool"))]
+            let count = || Ok(std::hint::black_box(count_chunks(&split_input(input, n, split))?).1);
+            time_reruns(n, reruns, stop, count)
+        }
+    }
+}
+
+// Times `count` up to `reruns` times, it returns the characters each thread counted
+#[cfg(feature = "bench")]
+fn time_reruns<F: FnMut() -> Result<Vec<u64>, CountError>>(n: usize, reruns: u32, stop: &AtomicBool, mut count: F) -> Result<BenchResult, CountError> {
+    let reruns = normalize_reruns(reruns);
+    // Grown as needed past that, a run asking for billions of reruns is likely to be interrupted
+    let mut times = Vec::with_capacity(reruns.min(1 << 16) as usize);
+    let mut work = Vec::new();
+    for _ in 0..reruns {   // Here reruns is a u32
+        let start = Instant::now();
+        work = count()?;
+        times.push(start.elapsed());
+        if stop.load(Ordering::Relaxed) {
+            break;
+        }
+    }
+    let reruns = times.len() as u32;
+    let latency = LatencyStats::from_times(&mut times);
+    let mean = latency.mean.as_secs_f64();
+    let variance = times.iter().map(|t| (t.as_secs_f64() - mean).powi(2)).sum::<f64>() / reruns as f64;
+    Ok(BenchResult {
+        threads: n,
+        reruns,
+        latency,
+        total: times.iter().sum(),
+        stddev: Duration::from_secs_f64(variance.sqrt()),
+        work,
+    })
+}
+
+/// Like [`benchmark`], but counting with `engine`. The serial engine runs on one thread whatever
+/// `n` is, and only [`Engine::Parallel`] reports the work of each thread, the others report
+/// their total as that of a single thread.
+///
+/// ```
+/// use par_calc::{benchmark_engine, Engine, Split};
+///
+/// let serial = benchmark_engine(&["abc", "cd"], Engine::Serial, 2, 3, Split::default()).unwrap();
+/// assert_eq!((serial.threads, serial.work), (1, vec![5]));
+/// let parallel = benchmark_engine(&["abc", "cd"], Engine::Parallel, 2, 3, Split::default()).unwrap();
+/// assert_eq!((parallel.threads, parallel.work), (2, vec![3, 2]));
+/// ```
+#[cfg(feature = "bench")]
+pub fn benchmark_engine(input: &[&str], engine: Engine, n: usize, reruns: u32, split: Split) -> Result<BenchResult, CountError> {
+    match engine {
+        Engine::Parallel => benchmark(input, n, reruns, split),
+        Engine::Serial => time_reruns(1, reruns, &AtomicBool::new(false), || Ok(vec![std::hint::black_box(engine.count(input, 1)?).total()])),
+        #[cfg(feature = "dashmap")]
+        Engine::Dashmap => time_reruns(n, reruns, &AtomicBool::new(false), || Ok(vec![std::hint::black_box(engine.count(input, n)?).total()])),
+    }
+}
+
+/// Runs [`benchmark`] for each of the thread counts, in the given order.
+///
+/// ```
+/// use par_calc::{benchmark_all, Split};
+///
+/// let results = benchmark_all(&["abc"; 10], &[1, 2, 4], 1, Split::default()).unwrap();
+/// assert_eq!(results.iter().map(|r| r.threads).collect::<Vec<_>>(), [1, 2, 4]);
+/// assert!(results.iter().all(|r| r.work.iter().sum::<u64>() == 30));
+/// ```
+#[cfg(feature = "bench")]
+pub fn benchmark_all<S: AsRef<str> + Sync>(input: &[S], threads: &[usize], reruns: u32, split: Split) -> Result<Vec<BenchResult>, CountError> {
+    benchmark_until(input, threads, reruns, split, &AtomicBool::new(false))
+}
+
+/// Like [`benchmark_all`], but stops early once `stop` is set. The thread count being measured at
+/// that point finishes its current rerun and is the last result, with fewer `reruns` than asked
+/// for, so the sweep has at least one result unless `threads` is empty.
+///
+/// ```
+/// use std::sync::atomic::AtomicBool;
+/// use par_calc::{benchmark_until, Split};
+///
+/// let results = benchmark_until(&["abc"], &[1, 2, 3, 4], 1, Split::default(), &AtomicBool::new(true)).unwrap();
+/// assert_eq!(results.len(), 1);
+/// ```
+#[cfg(feature = "bench")]
+pub fn benchmark_until<S: AsRef<str> + Sync>(input: &[S], threads: &[usize], reruns: u32, split: Split, stop: &AtomicBool) -> Result<Vec<BenchResult>, CountError> {
+    let mut results = Vec::new();
+    for &n in threads {
+        results.push(benchmark_interruptible(input, n, reruns, split, stop)?);
+        if stop.load(Ordering::Relaxed) {
+            break;
+        }
+    }
+    Ok(results)
+}
+
+/// Unit for [`format_duration`], so that a whole table of timings can share one.
+#[cfg(feature = "bench")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TimeUnit {
+    Ns,
+    Us,
+    Ms,
+    S,
+}
+
+#[cfg(feature = "bench")]
+impl TimeUnit {
+    /// The largest unit in which `nanos` is at least 1, e.g. for the fastest of several timings.
+    ///
+    /// ```
+    /// use par_calc::TimeUnit;
+    ///
+    /// assert_eq!(TimeUnit::fitting(999), TimeUnit::Ns);
+    /// assert_eq!(TimeUnit::fitting(234_500), TimeUnit::Us);
+    /// assert_eq!(TimeUnit::fitting(3_000_000_000), TimeUnit::S);
+    /// ```
+    pub fn fitting(nanos: u64) -> TimeUnit {
+        match nanos {
+            0..1_000 => TimeUnit::Ns,
+            1_000..1_000_000 => TimeUnit::Us,
+            1_000_000..1_000_000_000 => TimeUnit::Ms,
+            _ => TimeUnit::S,
+        }
+    }
+
+    fn nanos(self) -> u64 {
+        match self {
+            TimeUnit::Ns => 1,
+            TimeUnit::Us => 1_000,
+            TimeUnit::Ms => 1_000_000,
+            TimeUnit::S => 1_000_000_000,
+        }
+    }
+
+    fn suffix(self) -> &'static str {
+        match self {
+            TimeUnit::Ns => "ns",
+            TimeUnit::Us => "µs",
+            TimeUnit::Ms => "ms",
+            TimeUnit::S => "s",
+        }
+    }
+}
+
+/// Formats a duration given in nanoseconds in `unit`, with three decimals unless that is
+/// nanoseconds. Less than a nanosecond keeps its decimals too, so that it doesn't read as 0.
+///
+/// ```
+/// use par_calc::{format_duration, TimeUnit};
+///
+/// assert_eq!(format_duration(1_234_567.0, TimeUnit::Ms), "1.235 ms");
+/// assert_eq!(format_duration(1_234_567.0, TimeUnit::Us), "1234.567 µs");
+/// assert_eq!(format_duration(1_234_567.4, TimeUnit::Ns), "1234567 ns");
+/// assert_eq!(format_duration(0.25, TimeUnit::Ns), "0.250 ns");
+/// assert_eq!(format_duration(2_500_000.0, TimeUnit::S), "0.003 s");
+/// ```
+#[cfg(feature = "bench")]
+pub fn format_duration(nanos: f64, unit: TimeUnit) -> String {
+    match unit {
+        TimeUnit::Ns if nanos >= 1.0 || nanos == 0.0 => format!("{nanos:.0} ns"),
+        _ => format!("{:.3} {}", nanos / unit.nanos() as f64, unit.suffix()),
+    }
+}
+
+/// One input file of a [`RunReport`], after the lines were selected and filtered.
+#[cfg(feature = "serde")]
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct FileMeta {
+    /// Path as given on the command line
+    pub path: String,
+    /// Bytes of the lines that were counted, without their line endings
+    pub bytes: usize,
+    /// Lines that were counted
+    pub lines: usize,
+}
+
+/// What a [`RunReport`] counted, and the options that change the counts.
+#[cfg(feature = "serde")]
+#[derive(Clone, Debug, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct InputMeta {
+    /// The input files in the order they were given
+    pub files: Vec<FileMeta>,
+    /// Lines were lowercased before counting
+    pub fold_case: bool,
+    /// Whitespace was left out of the counts, false in reports from before the option existed
+    #[serde(default)]
+    pub no_whitespace: bool,
+    /// Locale of the case folding, None for the default one
+    pub locale: Option<String>,
+    /// 1-based first and last line of the line range, None for whole files
+    pub range: Option<(usize, usize)>,
+    /// Lines skipped at the start of each file
+    pub skip_first: usize,
+    /// Lines skipped at the end of each file
+    pub skip_last: usize,
+    /// Number of lines sampled from each file, None if all were counted
+    pub sample: Option<usize>,
+    /// Shortest line counted, in characters
+    pub min_len: usize,
+    /// Longest line counted, in characters, None without a limit
+    pub max_len: Option<usize>,
+    /// Encoding the files were decoded from, None for UTF-8
+    pub encoding: Option<String>,
+    /// How the lines were held in memory, "buffer" or "per-line"
+    pub loader: String,
+    /// Threads of the parallel count
+    pub threads: usize,
+    /// Privacy budget of the Laplace noise added to the counts, None for exact counts
+    pub dp_epsilon: Option<f64>,
+}
+
+/// Totals of a [`RunReport`]'s counts.
+#[cfg(feature = "serde")]
+#[derive(Clone, Copy, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct StatsSummary {
+    /// Sum of the counts
+    pub total_chars: u64,
+    /// Number of distinct characters
+    pub distinct: usize,
+    /// Shannon entropy in bits per character
+    pub entropy: f64,
+}
+
+#[cfg(feature = "serde")]
+impl StatsSummary {
+    /// Summarizes `counts`.
+    ///
+    /// ```
+    /// use par_calc::{count_chars, StatsSummary};
+    ///
+    /// let summary = StatsSummary::of(&count_chars(&["abab"]));
+    /// assert_eq!((summary.total_chars, summary.distinct, summary.entropy), (4, 2, 1.0));
+    /// ```
+    pub fn of(counts: &CharCounter) -> StatsSummary {
+        StatsSummary { total_chars: counts.total(), distinct: counts.distinct(), entropy: entropy(counts) }
+    }
+}
+
+/// The counts of one input file of a [`RunReport`], for `--per-file`.
+#[cfg(feature = "serde")]
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct FileCounts {
+    /// Path as given on the command line
+    pub path: String,
+    /// Occurrences of each character in this file
+    pub counts: BTreeMap<char, u64>,
+    /// Totals of this file's counts
+    pub stats: StatsSummary,
+}
+
+#[cfg(feature = "serde")]
+impl FileCounts {
+    pub fn new(path: &str, counts: &CharCounter) -> FileCounts {
+        FileCounts { path: path.to_string(), counts: counts.iter().map(|(&c, &n)| (c, n)).collect(), stats: StatsSummary::of(counts) }
+    }
+}
+
+/// Everything one run found, serializable so that it can be saved and read back. The counts are
+/// kept in code point order so that the same counts always serialize the same way.
+///
+/// ```
+/// use par_calc::{count_chars, InputMeta, RunReport};
+///
+/// let counts = count_chars(&["a𝄞a"]);
+/// let report = RunReport::new(InputMeta::default(), &counts);
+/// let json = serde_json::to_string(&report).unwrap();
+/// let read: RunReport = serde_json::from_str(&json).unwrap();
+/// assert_eq!(read.char_counts(), counts);
+/// assert_eq!(read.stats.total_chars, 3);
+/// ```
+#[cfg(feature = "serde")]
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct RunReport {
+    /// What was counted and how
+    pub input: InputMeta,
+    /// Occurrences of each character
+    pub counts: BTreeMap<char, u64>,
+    /// The benchmark sweep, None if the run didn't benchmark
+    #[cfg(feature = "bench")]
+    pub bench: Option<Vec<BenchResult>>,
+    /// Totals of the counts
+    pub stats: StatsSummary,
+    /// Each file's own counts when they were reported separately, empty otherwise
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub per_file: Vec<FileCounts>,
+}
+
+#[cfg(feature = "serde")]
+impl RunReport {
+    /// Builds a report on `counts`, summarizing them, without benchmark results.
+    pub fn new(input: InputMeta, counts: &CharCounter) -> RunReport {
+        RunReport {
+            input,
+            counts: counts.iter().map(|(&c, &n)| (c, n)).collect(),
+            #[cfg(feature = "bench")]
+            bench: None,
+            stats: StatsSummary::of(counts),
+            per_file: Vec::new(),
+        }
+    }
+
+    /// The counts as the counting functions return them.
+    pub fn char_counts(&self) -> CharCounter {
+        self.counts.iter().map(|(&c, &n)| (c, n)).collect()
+    }
+}
@@ -1,100 +1,1207 @@
+mod baseline;
+mod cli;
+mod compare;
+mod config;
+mod error;
+mod repeat;
+mod saved;
+mod serve;
+mod style;
+mod verify;
+mod watch;
+
 use std::cmp::max;
 use std::collections::HashMap;
-use std::fs::File;
-use std::io::{self, BufRead};
-use std::sync::mpsc::channel;
-use std::thread;
-use std::time::{Duration, Instant};
-use clap::{arg, command, value_parser};
+use std::fs::{File, OpenOptions};
+use std::io::{self, IsTerminal, Write};
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use clap_complete::Shell;
+use icu_casemap::CaseMapper;
+use serde::Deserialize;
+use cli::Options;
+use error::Error;
+use par_calc::{add_laplace_noise, benchmark_engine, benchmark_until, concentration_report, count_chars_ordered, count_chars_parallel_instrumented, count_chunks_with, count_cooccurrences, count_graphemes_bounded, count_non_whitespace, count_scripts, count_units, format_duration, list_freq, load_file, load_file_with_endings, load_lines, load_lines_as, sort_freq_by_order, split_input, top_contributing_lines_parallel, top_counts, BenchResult, CharCounter, CountEngine, Engine, FileCounts, FileMeta, InputMeta, Lines, LoadError, PairCounts, RunReport, SortKey, TimeUnit, Unit, UnitCounts};
+
+// Inserts a comma every three digits from the right, e.g. 10,000,000
+fn fmt_count(n: u64) -> String {
+    let digits = n.to_string();
+    let mut out = String::with_capacity(digits.len() + digits.len() / 3);
+    for (i, d) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            out.push(',');
+        }
+        out.push(d);
+    }
+    out
+}
 
-fn load_file(name: &str) -> Result<Vec<String>, io::Error> {
-    io::BufReader::new(File::open(name)?).lines().collect()
+fn fmt_num(n: u64, group: bool) -> String {
+    if group { fmt_count(n) } else { n.to_string() }
+}
+
+fn format_work(work: &[u64], opts: &Options) -> String {
+    let mut out = String::new();
+    for (index, chars) in work.iter().enumerate() {
+        out += &format!(" - thread {index}: {} characters\n", fmt_num(*chars, opts.group));
+    }
+    let min = work.iter().copied().min().unwrap_or(0);
+    let max = work.iter().copied().max().unwrap_or(0);
+    if min > 0 {
+        out += &format!("   imbalance (max/min): {:.2}x\n", max as f64 / min as f64);
+    }
+    out
 }
 
-fn count_chars<S: AsRef<str>>(input: &[S]) -> HashMap<char, usize> {
-    let mut counter = HashMap::<char, usize>::new();
-    for text in input {
-        for c in text.as_ref().chars() {
-            *counter.entry(c).or_default() += 1;
+fn fmt_time(nanos: f64, unit: TimeUnit, group: bool) -> String {
+    if group && unit == TimeUnit::Ns && nanos >= 1.0 {
+        format!("{} ns", fmt_count(nanos.round() as u64))
+    } else {
+        format_duration(nanos, unit)
+    }
+}
+
+fn format_bench(results: &[BenchResult], opts: &Options) -> String {
+    let mut out = String::new();
+    if opts.format == Format::Tsv {
+        for result in results {
+            out += &format!("{}\t{:.0}\n", result.threads, result.mean_nanos());
+        }
+        return out;
+    }
+    let fastest = results.iter().map(BenchResult::mean_nanos).reduce(f64::min);
+    // One unit and width for all rows so that the times line up, auto picks the fastest's unit
+    let unit = opts.time_unit.unwrap_or_else(|| TimeUnit::fitting(fastest.unwrap_or(0.0) as u64));
+    let times: Vec<String> = results.iter().map(|result| fmt_time(result.mean_nanos(), unit, opts.group)).collect();
+    let width = times.iter().map(|time| time.chars().count()).max().unwrap_or(0);
+    for (result, time) in results.iter().zip(times) {
+        let mut line = format!("Average time with {} threads: {time:>width$}", result.threads);
+        if opts.percentiles {
+            let l = &result.latency;
+            let [p50, p90, p95, p99, min, max] = [l.p50, l.p90, l.p95, l.p99, l.min, l.max].map(|t| fmt_time(t.as_nanos() as f64, unit, opts.group));
+            line += &format!(", p50 {p50}, p90 {p90}, p95 {p95}, p99 {p99}, min {min}, max {max}");
+        }
+        if results.len() > 1 && Some(result.mean_nanos()) == fastest {
+            line = opts.style.green(&line);
+        }
+        out += &line;
+        out.push('\n');
+        if opts.show_work {
+            out += &format_work(&result.work, opts);
         }
     }
-    counter
+    out
 }
 
-fn count_chars_parallel<S: AsRef<str> + Sync>(input: &[S], n: usize) -> HashMap<char, usize> {
-    let (sender, receiver) = channel();
-    let blcksz = (input.len() + n - 1) / n;
-    let mut counter = HashMap::<char, usize>::new();
-    thread::scope(|s| {
-        for chunk in input.chunks(blcksz) {
-            let sender = sender.clone();
-            s.spawn(move || {
-                let counter = count_chars(chunk);
-                sender.send(counter).unwrap();
-            });
+// --compare-strategies: one row per counting engine, the fastest in green
+fn format_engines(results: &[(Engine, BenchResult)], opts: &Options) -> String {
+    let mut out = String::new();
+    if opts.format == Format::Tsv {
+        for (engine, result) in results {
+            out += &format!("{}\t{:.0}\n", engine.name(), result.mean_nanos());
         }
+        return out;
+    }
+    let fastest = results.iter().map(|(_, result)| result.mean_nanos()).reduce(f64::min).unwrap_or(0.0);
+    let unit = opts.time_unit.unwrap_or_else(|| TimeUnit::fitting(fastest as u64));
+    let names: Vec<String> = results.iter().map(|(engine, _)| format!("{}:", engine.name())).collect();
+    let times: Vec<String> = results.iter().map(|(_, result)| fmt_time(result.mean_nanos(), unit, opts.group)).collect();
+    let name_width = names.iter().map(String::len).max().unwrap_or(0);
+    let width = times.iter().map(|time| time.chars().count()).max().unwrap_or(0);
+    out += &format!("Average time per engine with {} threads:\n", opts.max_threads);
+    for (((_, result), name), time) in results.iter().zip(names).zip(times) {
+        let ratio = if fastest > 0.0 { result.mean_nanos() / fastest } else { 1.0 };
+        let line = format!(" - {name:<name_width$} {time:>width$} ({ratio:.2}x the fastest)");
+        if results.len() > 1 && result.mean_nanos() == fastest {
+            out += &opts.style.green(&line);
+        } else {
+            out += &line;
+        }
+        out.push('\n');
+    }
+    out
+}
+
+// The timings are what bench was asked for, so --quiet keeps them. The human report has them on
+// `out` before the stats, the other formats keep `out` to the one table or document of the
+// stats and write them to stderr.
+fn write_bench(table: &str, opts: &Options, out: &mut dyn Write) -> io::Result<()> {
+    if opts.format == Format::Human {
+        out.write_all(table.as_bytes())
+    } else {
+        io::stderr().write_all(table.as_bytes())
+    }
+}
+
+// The whole sweep with enough about the input to make sense of it later, durations in nanoseconds
+fn bench_json(inputs: &[(&str, Input)], results: &[BenchResult], opts: &Options) -> String {
+    let lines: usize = inputs.iter().map(|(_, lines)| lines.len()).sum();
+    let bytes: usize = inputs.iter().map(|(_, lines)| lines.bytes()).sum();
+    let runs: Vec<_> = results.iter().map(|r| serde_json::json!({
+        "threads": r.threads,
+        "mean_ns": r.mean_nanos(),
+        "total_ns": r.total.as_nanos() as u64,
+        "chars_per_sec": r.chars_per_sec(),
+        "p50_ns": r.latency.p50.as_nanos() as u64,
+        "p90_ns": r.latency.p90.as_nanos() as u64,
+        "p95_ns": r.latency.p95.as_nanos() as u64,
+        "p99_ns": r.latency.p99.as_nanos() as u64,
+        "min_ns": r.latency.min.as_nanos() as u64,
+        "max_ns": r.latency.max.as_nanos() as u64,
+        "stddev_ns": r.stddev.as_nanos() as u64,
+        "work": r.work,
+    })).collect();
+    let doc = serde_json::json!({
+        "title": opts.title,
+        "files": inputs.iter().map(|(path, lines)| serde_json::json!({"path": path, "lines": lines.len()})).collect::<Vec<_>>(),
+        "total_lines": lines,
+        "total_bytes": bytes,
+        "total_chars": results.last().map_or(0, |r| r.work.iter().sum::<u64>()),
+        "reruns": opts.reruns,
+        "balance": format!("{:?}", opts.split.balance).to_lowercase(),
+        "results": runs,
     });
-    std::mem::drop(sender);
-    while let Ok(counter_part) = receiver.recv() {
-        for (key, value) in counter_part.iter() {
-            *counter.entry(*key).or_default() += value;
+    serde_json::to_string_pretty(&doc).unwrap() + "\n"
+}
+
+// Most frequent first, ties broken by char so output doesn't depend on HashMap order
+fn sorted_freq(counter: &CharCounter) -> Vec<(char, u64)> {
+    counter.top_n(usize::MAX)
+}
+
+// The `top` most frequent keys, only those are sorted however many there are
+fn sorted_by_count<'a, K: Ord + Clone + 'a>(counter: impl IntoIterator<Item = (&'a K, &'a u64)>, top: usize) -> Vec<(K, u64)> {
+    top_counts(counter.into_iter().map(|(k, &n)| (k.clone(), n)), top)
+}
+
+fn char_name(c: char) -> Option<&'static str> {
+    Some(match c {
+        '\u{00A0}' => "NBSP",
+        '\u{00AD}' => "SHY",
+        '\u{2007}' => "FIGURE SPACE",
+        '\u{202F}' => "NNBSP",
+        '\u{200B}' => "ZWSP",
+        '\u{200C}' => "ZWNJ",
+        '\u{200D}' => "ZWJ",
+        '\u{2060}' => "WJ",
+        '\u{FEFF}' => "BOM",
+        _ => return None,
+    })
+}
+
+// Quoted char with invisible or layout-breaking ones spelled out, e.g. '\t' (U+0009)
+fn display_char(c: char) -> String {
+    let escaped: String = if c == '"' { c.to_string() } else { c.escape_debug().collect() };
+    if escaped.starts_with("\\u") {
+        let code = format!("\\u{{{:04X}}}", c as u32);
+        match char_name(c) {
+            Some(name) => format!("'{code}' {name}"),
+            None => format!("'{code}' (U+{:04X})", c as u32),
         }
+    } else if c.is_control() {
+        format!("'{escaped}' (U+{:04X})", c as u32)
+    } else {
+        format!("'{escaped}'")
     }
-    counter
 }
 
-fn benchmark<S: AsRef<str> + Sync>(input: &[S], n: usize, reruns: u32) -> (Duration, HashMap<char, usize>) {
-    let start = Instant::now();
-    let mut counter = None;
-    for _ in 0..reruns {   // Here reruns is a u32
-        counter = Some(count_chars_parallel(input, n));
+// --print-codepoints labels, e.g. U+002D '-' (HYPHEN-MINUS), for telling lookalikes apart
+fn codepoint_char(c: char) -> String {
+    let escaped: String = if c == '"' { c.to_string() } else { c.escape_debug().collect() };
+    match unicode_names2::name(c) {
+        Some(name) => format!("U+{:04X} '{escaped}' ({name})", c as u32),
+        None => format!("U+{:04X} '{escaped}'", c as u32),
+    }
+}
+
+// Diagnostics always go to stderr so that stdout only has the results
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Verbosity {
+    Quiet,
+    Normal,
+    Verbose,
+}
+
+impl Verbosity {
+    fn info(self, message: &str) {
+        if self >= Verbosity::Normal {
+            eprintln!("{message}");
+        }
+    }
+
+    fn detail(self, message: &str) {
+        if self >= Verbosity::Verbose {
+            eprintln!("{message}");
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Format {
+    Human,
+    Csv,
+    Json,
+    Jsonl,
+    Tsv,
+}
+
+fn csv_field(field: &str) -> String {
+    format!("\"{}\"", field.replace('"', "\"\""))
+}
+
+// The character itself unless it is a control character, which would break the line or the columns
+fn tsv_char(c: char) -> String {
+    if c.is_control() { format!("<U+{:04X}>", c as u32) } else { c.to_string() }
+}
+
+fn json_char(c: char) -> String {
+    serde_json::to_string(&c.to_string()).unwrap()
+}
+
+#[derive(Debug)]
+struct Histogram {
+    log: bool,
+    ascii: bool,
+}
+
+const EIGHTHS: [char; 8] = [' ', '▏', '▎', '▍', '▌', '▋', '▊', '▉'];
+
+// A bar of up to `width` cells, `fill` in 0..=1, with eighth-cell resolution unless ascii
+fn bar(fill: f64, width: usize, ascii: bool) -> String {
+    let eighths = (fill.clamp(0.0, 1.0) * (width * 8) as f64).round() as usize;
+    if ascii {
+        return "#".repeat(eighths.div_ceil(8));
+    }
+    let mut out = "█".repeat(eighths / 8);
+    let rest = eighths % 8;
+    if rest > 0 {
+        out.push(EIGHTHS[rest]);
+    }
+    out
+}
+
+fn terminal_width() -> usize {
+    std::env::var("COLUMNS").ok().and_then(|c| c.parse().ok()).unwrap_or(80)
+}
+
+fn utf8_terminal() -> bool {
+    let utf8 = ["LC_ALL", "LC_CTYPE", "LANG"]
+        .iter()
+        .find_map(|var| std::env::var(var).ok().filter(|v| !v.is_empty()))
+        .is_some_and(|locale| locale.to_uppercase().replace('-', "").contains("UTF8"));
+    utf8 && io::stdout().is_terminal()
+}
+
+fn append_bars(lines: Vec<String>, counts: &[u64], histogram: &Histogram) -> String {
+    let label_width = lines.iter().map(|l| l.chars().count()).max().unwrap_or(0);
+    let width = max(terminal_width().saturating_sub(label_width + 1), 10);
+    let top = counts.iter().copied().max().unwrap_or(0).max(1) as f64;
+    let ascii = histogram.ascii || !utf8_terminal();
+    let mut out = String::new();
+    for (line, &n) in lines.iter().zip(counts) {
+        let fill = if histogram.log { (n as f64).ln_1p() / top.ln_1p() } else { n as f64 / top };
+        let padding = label_width - line.chars().count();
+        out += &format!("{line}{} {}\n", " ".repeat(padding), bar(fill, width, ascii));
+    }
+    out
+}
+
+// `missing` placeholder rows follow the characters, for --pad-missing. `file` is the input the
+// counts are of with --per-file, None for the combined counts.
+fn format_stats(heading: &str, freq: &[(char, u64)], missing: usize, file: Option<&str>, opts: &Options) -> String {
+    let mut out = String::new();
+    // Records carry the title so that the output of several runs can be concatenated
+    let csv_title = opts.title.as_ref().map_or(String::new(), |title| csv_field(title) + ",");
+    let json_title = opts.title.as_ref().map_or(String::new(), |title| format!("\"title\":{},", serde_json::to_string(title).unwrap()));
+    // and with --per-file the file, empty or null for the combined counts
+    let file = opts.per_file.then_some(file);
+    let csv_title = csv_title + &file.map_or(String::new(), |file| csv_field(file.unwrap_or("")) + ",");
+    let json_title = json_title + &match file {
+        Some(Some(file)) => format!("\"file\":{},", serde_json::to_string(file).unwrap()),
+        Some(None) => "\"file\":null,".to_string(),
+        None => String::new(),
+    };
+    let tsv_file = file.map_or(String::new(), |file| format!("{}\t", file.unwrap_or("")));
+    match opts.format {
+        Format::Human => {
+            out += &format!("{heading}:\n");
+            let label = if opts.codepoints { codepoint_char } else { display_char };
+            let labels: Vec<String> = freq.iter().map(|&(c, _)| label(c)).collect();
+            let counts: Vec<String> = freq.iter().map(|&(_, n)| fmt_num(n, opts.group)).collect();
+            // With --zero-pad the counts form a column as wide as the widest of them
+            let label_width = if opts.zero_pad { labels.iter().map(|l| l.chars().count()).max().unwrap_or(0) } else { 0 };
+            let count_width = if opts.zero_pad { counts.iter().map(String::len).max().unwrap_or(0) } else { 0 };
+            let lines: Vec<String> = labels
+                .iter()
+                .zip(&counts)
+                .map(|(label, count)| {
+                    let gap = " ".repeat(label_width.saturating_sub(label.chars().count()));
+                    // Leading zeros and thousands separators don't mix, grouped counts get spaces
+                    let count = if opts.group { format!("{count:>count_width$}") } else { format!("{count:0>count_width$}") };
+                    format!(" - {}:{gap} {count} occurrences", opts.style.bold(label))
+                })
+                .collect();
+            match &opts.histogram {
+                Some(histogram) => {
+                    let counts: Vec<u64> = freq.iter().map(|&(_, n)| n).collect();
+                    out += &append_bars(lines, &counts, histogram);
+                }
+                None => lines.iter().for_each(|line| out += &format!("{line}\n")),
+            }
+            out += &" - (missing)\n".repeat(missing);
+        }
+        Format::Csv => {
+            out += if opts.title.is_some() { "title," } else { "" };
+            out += if file.is_some() { "file,char,count\n" } else { "char,count\n" };
+            for (c, n) in freq {
+                out += &format!("{csv_title}{},{n}\n", csv_field(&c.to_string()));
+            }
+            out += &format!("{csv_title},0\n").repeat(missing);
+        }
+        Format::Json => {
+            let mut entries: Vec<_> = freq.iter().map(|(c, n)| format!("{{{json_title}\"char\":{},\"count\":{n}}}", json_char(*c))).collect();
+            entries.extend(std::iter::repeat_n(format!("{{{json_title}\"char\":null,\"count\":0}}"), missing));
+            out += &format!("[{}]\n", entries.join(","));
+        }
+        Format::Jsonl => {
+            for (c, n) in freq {
+                out += &format!("{{{json_title}\"char\":{},\"count\":{n}}}\n", json_char(*c));
+            }
+            out += &format!("{{{json_title}\"char\":null,\"count\":0}}\n").repeat(missing);
+        }
+        Format::Tsv => {
+            for (c, n) in freq {
+                out += &format!("{tsv_file}{}\t{}\t{n}\n", *c as u32, tsv_char(*c));
+            }
+            out += &format!("{tsv_file}\t\t0\n").repeat(missing);
+        }
+    }
+    out
+}
+
+// Says so when fewer than `wanted` characters exist, "all" being usize::MAX never counts as more
+fn format_ranked(heading: &str, freq: &[(char, u64)], wanted: usize, file: Option<&str>, opts: &Options) -> String {
+    let short = if wanted == usize::MAX { 0 } else { wanted.saturating_sub(freq.len()) };
+    let mut out = format_stats(heading, freq, if opts.pad_missing { short } else { 0 }, file, opts);
+    if short > 0 {
+        let note = format!("(only {} distinct characters present)", freq.len());
+        if opts.format == Format::Human {
+            out += &format!("{note}\n");
+        } else {
+            opts.verbosity.info(&note);
+        }
+    }
+    out
+}
+
+struct FreqDiff {
+    c: char,
+    count_a: u64,
+    count_b: u64,
+    pct_a: f64,
+    pct_b: f64,
+}
+
+impl FreqDiff {
+    fn delta(&self) -> f64 {
+        self.pct_b - self.pct_a
+    }
+}
+
+fn percent(n: u64, total: u64) -> f64 {
+    if total == 0 { 0.0 } else { n as f64 * 100.0 / total as f64 }
+}
+
+// Characters whose share differs by more than `threshold` percentage points (or that are missing
+// from one side), largest difference first, plus the total variation distance of the two
+fn compare_freq(a: &CharCounter, b: &CharCounter, threshold: f64) -> (Vec<FreqDiff>, f64) {
+    let total_a = a.total();
+    let total_b = b.total();
+    let mut chars: Vec<char> = a.keys().chain(b.keys()).copied().collect();
+    chars.sort_unstable();
+    chars.dedup();
+    let mut distance = 0.0;
+    let mut diffs = Vec::new();
+    for c in chars {
+        let count_a = a.get(&c).copied().unwrap_or(0);
+        let count_b = b.get(&c).copied().unwrap_or(0);
+        let diff = FreqDiff { c, count_a, count_b, pct_a: percent(count_a, total_a), pct_b: percent(count_b, total_b) };
+        distance += diff.delta().abs() / 200.0;
+        if diff.delta().abs() > threshold || count_a == 0 || count_b == 0 {
+            diffs.push(diff);
+        }
+    }
+    diffs.sort_by(|x, y| y.delta().abs().total_cmp(&x.delta().abs()).then(x.c.cmp(&y.c)));
+    (diffs, distance)
+}
+
+fn format_compare(diffs: &[FreqDiff], distance: f64, other: &str, group: bool) -> String {
+    let mut out = format!("Differences against {other}:\n");
+    for d in diffs {
+        out += &format!(
+            " - {}: {} ({:.2}%) vs {} ({:.2}%), delta {:+.2} pp\n",
+            display_char(d.c), fmt_num(d.count_a, group), d.pct_a, fmt_num(d.count_b, group), d.pct_b, d.delta()
+        );
+    }
+    out += &format!("Total variation distance: {distance:.6}\n");
+    out
+}
+
+const ENGLISH_FREQ: [(char, f64); 26] = [
+    ('a', 0.08167), ('b', 0.01492), ('c', 0.02782), ('d', 0.04253), ('e', 0.12702), ('f', 0.02228),
+    ('g', 0.02015), ('h', 0.06094), ('i', 0.06966), ('j', 0.00153), ('k', 0.00772), ('l', 0.04025),
+    ('m', 0.02406), ('n', 0.06749), ('o', 0.07507), ('p', 0.01929), ('q', 0.00095), ('r', 0.05987),
+    ('s', 0.06327), ('t', 0.09056), ('u', 0.02758), ('v', 0.00978), ('w', 0.02360), ('x', 0.00150),
+    ('y', 0.01974), ('z', 0.00074),
+];
+
+fn invalid_data(message: String) -> Error {
+    Error::InvalidData(message)
+}
+
+fn parse_reference_char(field: &str, path: &str) -> Result<char, Error> {
+    let field = field.trim();
+    let field = field.strip_prefix('"').and_then(|f| f.strip_suffix('"')).unwrap_or(field);
+    let mut chars = field.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Ok(c),
+        _ => Err(invalid_data(format!("{path}: expected a single character, got '{field}'"))),
+    }
+}
+
+// Reads `char -> expected relative frequency` from a JSON object or `char,frequency` CSV lines
+fn load_reference(path: &str) -> Result<Vec<(char, f64)>, Error> {
+    if path == "english" {
+        return Ok(ENGLISH_FREQ.to_vec());
     }
-    (Instant::elapsed(&start) / reruns, counter.unwrap())
+    let text = std::fs::read_to_string(path).map_err(|e| Error::open(path, e))?;
+    let mut reference = Vec::new();
+    if text.trim_start().starts_with('{') {
+        let map: HashMap<String, f64> = serde_json::from_str(&text).map_err(|e| invalid_data(format!("{path}: {e}")))?;
+        for (key, freq) in map {
+            reference.push((parse_reference_char(&key, path)?, freq));
+        }
+    } else {
+        for (index, line) in text.lines().enumerate() {
+            let Some((key, freq)) = line.rsplit_once(',') else { continue };
+            let Ok(freq) = freq.trim().parse::<f64>() else {
+                if index == 0 {
+                    continue; // header
+                }
+                return Err(invalid_data(format!("{path}:{}: invalid frequency '{}'", index + 1, freq.trim())));
+            };
+            reference.push((parse_reference_char(key, path)?, freq));
+        }
+    }
+    let sum: f64 = reference.iter().map(|&(_, freq)| freq).sum();
+    if (sum - 1.0).abs() > 0.01 {
+        return Err(invalid_data(format!("{path}: reference frequencies sum to {sum}, expected 1.0")));
+    }
+    reference.sort_unstable_by_key(|&(c, _)| c);
+    Ok(reference)
 }
 
-fn benchmark_all<S: AsRef<str> + Sync>(input: &[S], max: usize, reruns: u32) -> HashMap<char, usize> {
-    let mut counter = None;
-    for par_level in 1..max+1 {
-        let (time, counter1) = benchmark(input, par_level, reruns);
-        counter = Some(counter1);
-        println!("Average time with {par_level} threads: {:?}", time);
+// Observed shares are relative to the characters covered by the reference, everything else is
+// reported as one "unexpected" bucket
+fn format_reference(stats: &CharCounter, reference: &[(char, f64)], name: &str, group: bool) -> String {
+    let covered: u64 = reference.iter().filter_map(|(c, _)| stats.get(c)).sum();
+    let total = stats.total();
+    let mut out = format!("Comparison against reference {name}:\n");
+    let mut chi_square = 0.0;
+    for &(c, expected) in reference {
+        let count = stats.get(&c).copied().unwrap_or(0);
+        let observed = if covered == 0 { 0.0 } else { count as f64 / covered as f64 };
+        let expected_count = expected * covered as f64;
+        if expected_count > 0.0 {
+            chi_square += (count as f64 - expected_count).powi(2) / expected_count;
+        }
+        let ratio = if expected > 0.0 { format!("{:.2}", observed / expected) } else { "-".to_string() };
+        out += &format!(
+            " - {}: observed {:.3}%, expected {:.3}%, ratio {ratio}\n",
+            display_char(c), observed * 100.0, expected * 100.0
+        );
     }
-    counter.unwrap()
+    out += &format!(
+        "Unexpected characters: {} occurrences ({:.2}% of all characters)\n",
+        fmt_num(total - covered, group), percent(total - covered, total)
+    );
+    out += &format!("Chi-square: {chi_square:.4}\n");
+    out
 }
 
-fn main() -> Result<(), io::Error> {
-    let matches = command!() // requires `cargo` feature
-    .arg(arg!(<FILE> "File to operate on"))
-    .arg(arg!(-m --max <MAX> "Maximum number of threads to benchmark").required(false).value_parser(value_parser!(usize)).default_value("8"))
-    .arg(arg!(-r --reruns <RERUNS>  "The number of reruns to run each test").value_parser(value_parser!(u32)).default_value("100"))
-    .arg(arg!(-s --stats <rank>   "Display statistics").value_parser(value_parser!(usize)))
-    .get_matches();
+// The lines of one input file, in one buffer or with --loader per-line as a string each, the way
+// load_file has always read them
+enum Input {
+    Buffer(Lines),
+    PerLine(Vec<String>),
+}
 
-    let mut max_threads = *matches.get_one::<usize>("max").unwrap();
-    if max_threads == 0 {
-        println!("Max thread argument is equal to zero, setting to 1.");
-        max_threads = 1;
+impl Input {
+    fn len(&self) -> usize {
+        match self {
+            Input::Buffer(lines) => lines.len(),
+            Input::PerLine(lines) => lines.len(),
+        }
     }
-    let mut reruns = *matches.get_one::<u32>("reruns").unwrap();
-    if reruns == 0 {
-        println!("Reruns argument is equal to zero, setting to 1.");
-        reruns = 1;
+
+    // Without line endings, tracked while loading except for --loader per-line
+    fn bytes(&self) -> usize {
+        match self {
+            Input::Buffer(lines) => lines.bytes(),
+            Input::PerLine(lines) => lines.iter().map(String::len).sum(),
+        }
     }
 
-    let lines = load_file(matches.get_one::<String>("FILE").unwrap())?;
+    fn strs(&self) -> Vec<&str> {
+        match self {
+            Input::Buffer(lines) => lines.strs(),
+            Input::PerLine(lines) => lines.iter().map(String::as_str).collect(),
+        }
+    }
 
-    let stats = benchmark_all(lines.as_slice(), max_threads, reruns);
+    fn retain(&mut self, mut f: impl FnMut(&str) -> bool) {
+        match self {
+            Input::Buffer(lines) => lines.retain(f),
+            Input::PerLine(lines) => lines.retain(|line| f(line)),
+        }
+    }
 
-    if let Some(rank) = matches.get_one::<usize>("stats") {
-        if *rank == 0 {
-            println!("Stats argument is used, but rank set to 0. Setting to 1.");
+    // Keeps the lines at `indices`, in that order
+    fn select(&mut self, indices: impl IntoIterator<Item = usize>) {
+        match self {
+            Input::Buffer(lines) => lines.select(indices),
+            Input::PerLine(lines) => *lines = indices.into_iter().map(|i| std::mem::take(&mut lines[i])).collect(),
         }
-        let mut freq : Vec<_> = stats.into_iter().collect();
-        freq.sort_unstable_by_key(|&(_, n)| n);
-        println!("Most frequent characters:");
-        for (c, n) in freq.into_iter().rev().take(max(*rank, 1)) {
-            println!(" - '{c}': {n} occurrences");
+    }
+
+    fn map(&mut self, f: impl Fn(&str) -> String) {
+        match self {
+            Input::Buffer(lines) => *lines = lines.iter().map(f).collect(),
+            Input::PerLine(lines) => lines.iter_mut().for_each(|line| *line = f(line)),
+        }
+    }
+}
+
+// Repeatable with --seed
+fn seeded_rng(opts: &Options) -> StdRng {
+    match opts.seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    }
+}
+
+// --dp-epsilon noise, added to every set of counts before it is reported
+fn privatize(counts: &mut CharCounter, rng: &mut StdRng, opts: &Options) {
+    if let Some(epsilon) = opts.dp_epsilon {
+        add_laplace_noise(counts, epsilon, rng);
+    }
+}
+
+// Loads one input file and applies the line selection options to it
+fn load_input(path: &str, opts: &Options) -> Result<Input, Error> {
+    let (mut lines, endings) = match (opts.encoding, opts.per_line) {
+        (None, true) if opts.line_endings => {
+            let (lines, endings) = load_file_with_endings(path, opts.range)?;
+            (Input::PerLine(lines), Some(endings))
+        }
+        (None, true) => (Input::PerLine(load_file(path, opts.range)?), None),
+        (encoding, per_line) => {
+            let lines = match encoding {
+                Some(encoding) => load_lines_as(path, opts.range, encoding)?,
+                None => load_lines(path, opts.range)?,
+            };
+            let endings = opts.line_endings.then(|| lines.line_endings());
+            (if per_line { Input::PerLine(lines.iter().map(str::to_string).collect()) } else { Input::Buffer(lines) }, endings)
+        }
+    };
+    if let Some(e) = endings {
+        let mixed = if e.mixed { ", mixed" } else { "" };
+        opts.verbosity.info(&format!("Line endings of {path}: {} LF, {} CRLF, {} CR{mixed}", e.lf, e.crlf, e.cr));
+    }
+    if opts.skip_first > 0 || opts.skip_last > 0 {
+        if opts.skip_first + opts.skip_last >= lines.len() {
+            return Err(Error::Usage(format!(
+                "skipping {} first and {} last lines leaves nothing of the {} lines of '{path}'",
+                opts.skip_first, opts.skip_last, lines.len()
+            )));
+        }
+        lines.select(opts.skip_first..lines.len() - opts.skip_last);
+    }
+
+    if let Some(n) = opts.sample {
+        if n < lines.len() {
+            let indices: Vec<usize> = (0..lines.len()).collect();
+            lines.select(indices.choose_multiple(&mut seeded_rng(opts), n).copied());
+            opts.verbosity.info(&format!("Counting a sample of {n} lines, results are approximate."));
+        }
+    }
+
+    if opts.min_len > 0 || opts.max_len < usize::MAX {
+        let before = lines.len();
+        lines.retain(|line| (opts.min_len..=opts.max_len).contains(&line.chars().count()));
+        opts.verbosity.info(&format!("Dropped {} lines outside the length limits.", before - lines.len()));
+    }
+
+    if opts.fold_case {
+        lines.map(|line| match &opts.locale {
+            Some(locale) => CaseMapper::new().lowercase_to_string(line, locale).into_owned(),
+            None => line.to_lowercase(),
+        });
+    }
+    Ok(lines)
+}
+
+// The characters the stats listing shows, in its order, and how many were asked for. With `order`
+// ties in count are broken by it instead of by code point.
+fn listed_freq(stats: &CharCounter, order: Option<&[char]>, opts: &Options) -> (Vec<(char, u64)>, usize) {
+    let wanted = if opts.top_percent.is_some() { usize::MAX } else { opts.rank.unwrap_or(usize::MAX) };
+    (list_freq(stats, opts.sort, order, opts.top_percent, wanted), wanted)
+}
+
+// Writes the summary and stats table for one set of counts to `out`
+fn write_report(label: Option<&str>, file: Option<&str>, lines: usize, stats: &CharCounter, order: Option<&[char]>, opts: &Options, out: &mut dyn Write) -> Result<(), io::Error> {
+    let group = opts.group;
+    let mut report = String::new();
+    if let Some(label) = label {
+        // Keep machine-readable output parseable
+        if opts.format == Format::Human {
+            report += &format!("=== {label} ===\n");
+        } else {
+            opts.verbosity.info(&format!("=== {label} ==="));
+        }
+    }
+    if opts.summary {
+        let summary = format!(
+            "Total lines: {}, Total characters: {}\n",
+            fmt_num(lines as u64, group), fmt_num(stats.total(), group)
+        );
+        if opts.format == Format::Human {
+            report += &summary;
+        } else {
+            opts.verbosity.info(summary.trim_end());
+        }
+    }
+    // Machine-readable formats dump the whole table unless a rank is given
+    if opts.rank.is_some() || opts.top_percent.is_some() || opts.format != Format::Human {
+        let (freq, wanted) = listed_freq(stats, order, opts);
+        let heading = if opts.sort == SortKey::CountDesc { "Most frequent characters" } else { "Characters" };
+        report += &format_ranked(heading, &freq, wanted, file, opts);
+    }
+    if let Some(bottom) = opts.bottom {
+        let freq = match order {
+            Some(order) => {
+                let mut freq = sorted_freq(stats);
+                sort_freq_by_order(&mut freq, SortKey::CountAsc, order);
+                freq.truncate(bottom);
+                freq
+            }
+            None => stats.bottom_n(bottom),
+        };
+        report += &format_ranked("Least frequent characters", &freq, bottom, file, opts);
+    }
+    out.write_all(report.as_bytes())
+}
+
+// Tier 0 holds the characters seen once, tier k those seen 10^(k-1)+1 to 10^k times
+fn format_tiers(stats: &CharCounter, group: bool) -> String {
+    let mut tiers = Vec::new();
+    for &n in stats.values() {
+        let (mut tier, mut upper) = (0, 1);
+        while n > upper {
+            tier += 1;
+            upper *= 10;
+        }
+        if tiers.len() <= tier {
+            tiers.resize(tier + 1, 0);
+        }
+        tiers[tier] += 1;
+    }
+    let mut out = "Frequency tiers:\n".to_string();
+    let mut upper = 1;
+    for (tier, chars) in tiers.into_iter().enumerate() {
+        let range = if tier == 0 { "1".to_string() } else { format!("{}-{}", fmt_num(upper / 10 + 1, group), fmt_num(upper, group)) };
+        out += &format!(" - {range} occurrences: {} characters\n", fmt_num(chars as u64, group));
+        upper *= 10;
+    }
+    out
+}
+
+fn format_concentration(stats: &CharCounter, group: bool) -> String {
+    let mut out = String::new();
+    for (percent, chars) in concentration_report(stats).levels {
+        out += &format!("{percent}% of characters covered by top {} chars\n", fmt_num(chars as u64, group));
+    }
+    out
+}
+
+fn format_scripts(stats: &CharCounter, group: bool) -> String {
+    let total = stats.total();
+    let mut out = "Characters per script:\n".to_string();
+    for (script, n) in sorted_by_count(&count_scripts(stats), usize::MAX) {
+        out += &format!(" - {script}: {} ({:.2}%)\n", fmt_num(n, group), percent(n, total));
+    }
+    out
+}
+
+fn format_cooccurrences(pairs: &PairCounts, opts: &Options) -> String {
+    let mut out = "Characters most often on the same line:\n".to_string();
+    for ((a, b), n) in sorted_by_count(pairs, opts.rank.unwrap_or(10)) {
+        out += &format!(" - {} and {}: {} lines\n", display_char(a), display_char(b), fmt_num(n, opts.group));
+    }
+    out
+}
+
+// `top` indexes the lines of all inputs together, they are listed by file and line in that file
+fn format_top_lines(inputs: &[(&str, Input)], target: char, top: &[(usize, usize)], opts: &Options) -> String {
+    let mut out = format!("Lines with the most {}:\n", display_char(target));
+    for &(index, n) in top {
+        let (mut path, mut line) = ("", index);
+        for (name, lines) in inputs {
+            path = name;
+            if line < lines.len() {
+                break;
+            }
+            line -= lines.len();
+        }
+        out += &format!(" - {path}:{}: {} occurrences\n", line + 1, fmt_num(n as u64, opts.group));
+    }
+    out
+}
+
+// With --max-keys the graphemes get a bounded pass of their own
+fn count_all_units(all: &[&str], opts: &Options) -> Result<UnitCounts, Error> {
+    let Some(max_keys) = opts.max_keys.filter(|_| opts.units.contains(&Unit::Grapheme)) else {
+        return Ok(count_units(all, &opts.units));
+    };
+    let units: Vec<Unit> = opts.units.iter().copied().filter(|&unit| unit != Unit::Grapheme).collect();
+    let mut counts = count_units(all, &units);
+    let bounded = count_graphemes_bounded(all, max_keys).map_err(|e| Error::write(&std::env::temp_dir().display().to_string(), e))?;
+    if bounded.dropped > 0 {
+        opts.verbosity.info(&format!("{} occurrences of rare graphemes were spilled past --max-keys {max_keys} and are not counted", fmt_num(bounded.dropped, opts.group)));
+    }
+    counts.graphemes = Some(bounded.graphemes);
+    Ok(counts)
+}
+
+fn format_units(counts: &UnitCounts, opts: &Options) -> String {
+    let mut out = String::new();
+    let top = opts.rank.unwrap_or(usize::MAX);
+    let mut section = |heading: &str, freq: Vec<(String, u64)>| {
+        out += &format!("{heading}:\n");
+        for (key, n) in &freq {
+            out += &format!(" - {key}: {} occurrences\n", fmt_num(*n, opts.group));
+        }
+    };
+    if let Some(graphemes) = &counts.graphemes {
+        let freq = sorted_by_count(graphemes, top).into_iter().map(|(g, n)| (format!("{g:?}"), n)).collect();
+        section("Graphemes", freq);
+    }
+    if let Some(chars) = &counts.chars {
+        section("Characters", chars.top_n(top).into_iter().map(|(c, n)| (display_char(c), n)).collect());
+    }
+    if let Some(bytes) = &counts.bytes {
+        section("Bytes", sorted_by_count(bytes, top).into_iter().map(|(b, n)| (format!("0x{b:02X}"), n)).collect());
+    }
+    out
+}
+
+// Counts with the --engine, --no-whitespace leaves the whitespace out in the counting loop of the
+// serial and the parallel one
+fn count_stats(lines: &[&str], opts: &Options) -> Result<CharCounter, Error> {
+    match opts.engine {
+        _ if !opts.no_whitespace => Ok(opts.engine.count(lines, opts.max_threads)?),
+        Engine::Serial => Ok(count_non_whitespace(lines)),
+        Engine::Parallel => Ok(count_chunks_with(&split_input(lines, opts.max_threads, opts.split), count_non_whitespace)?.0),
+        #[cfg(feature = "dashmap")]
+        Engine::Dashmap => Err(Error::Usage("--no-whitespace doesn't work with --engine dashmap".to_string())),
+    }
+}
+
+// One JSON line per --window, with the characters of the stats listing
+fn window_json(window: usize, (first, last): (usize, usize), counts: &CharCounter, opts: &Options) -> String {
+    let title = opts.title.as_ref().map_or(String::new(), |title| format!("\"title\":{},", serde_json::to_string(title).unwrap()));
+    let entries: Vec<String> = listed_freq(counts, None, opts).0.iter().map(|(c, n)| format!("{{\"char\":{},\"count\":{n}}}", json_char(*c))).collect();
+    format!(
+        "{{{title}\"window\":{window},\"first_line\":{first},\"last_line\":{last},\"total_chars\":{},\"counts\":[{}]}}\n",
+        counts.total(), entries.join(",")
+    )
+}
+
+// --window: a report per `size` lines, numbered across all the inputs, instead of one of them all
+fn run_windows(all: &[&str], size: usize, opts: &Options, out: &mut dyn Write) -> Result<(), Error> {
+    if !matches!(opts.format, Format::Human | Format::Jsonl) {
+        return Err(Error::Usage("--window needs --format human or jsonl".to_string()));
+    }
+    let mut rng = seeded_rng(opts);
+    for (i, window) in all.chunks(size).enumerate() {
+        let mut counts = count_stats(window, opts)?;
+        privatize(&mut counts, &mut rng, opts);
+        let lines = (i * size + 1, i * size + window.len());
+        match opts.format {
+            Format::Jsonl => out.write_all(window_json(i + 1, lines, &counts, opts).as_bytes())?,
+            _ => write_report(Some(&format!("Lines {}-{}", lines.0, lines.1)), None, window.len(), &counts, None, opts, out)?,
         }
     }
     Ok(())
-}
\ No newline at end of file
+}
+
+// Counts (or benchmarks) all inputs together and reports on them, writing the result to `out` and
+// the timings and other diagnostics to stderr. With --per-file each input gets its own section
+// before the combined one.
+fn run(inputs: &[(&str, Input)], opts: &Options, out: &mut dyn Write) -> Result<(), Error> {
+    let group = opts.group;
+    let all: Vec<&str> = inputs.iter().flat_map(|(_, lines)| lines.strs()).collect();
+    if opts.format == Format::Human {
+        writeln!(out, "=== {} ===", title(inputs, opts))?;
+    }
+    if let Some(size) = opts.window {
+        return run_windows(&all, size, opts, out);
+    }
+    let chunks: Vec<usize> = split_input(&all, opts.max_threads, opts.split).iter().map(|chunk| chunk.len()).collect();
+    opts.verbosity.detail(&format!("Splitting {} lines between {} threads, lines per thread: {chunks:?}", all.len(), chunks.len()));
+    // With --chunk-lines the size depends on the thread count, auto gives more threads smaller chunks
+    for &n in opts.sweep.iter().filter(|_| opts.bench) {
+        if let Some(lines) = opts.split.chunk_size.lines_for(all.len(), n) {
+            opts.verbosity.detail(&format!("Benchmarking {n} threads with {} chunks of {lines} lines", all.len().div_ceil(lines)));
+        }
+    }
+    // A regression against --baseline fails the run only after the report is written
+    let mut verdict = Ok(());
+    let mut bench = None;
+    if opts.bench && opts.compare_strategies {
+        let mut results = Vec::new();
+        for engine in Engine::available() {
+            results.push((engine, benchmark_engine(&all, engine, opts.max_threads, opts.reruns, opts.split)?));
+        }
+        write_bench(&format_engines(&results, opts), opts, out)?;
+    } else if opts.bench {
+        if opts.percentiles && opts.reruns < 100 {
+            opts.verbosity.info(&format!("warning: with only {} reruns the p99 is the slowest rerun, use --reruns 100 or more for meaningful tails", opts.reruns));
+        }
+        // Ctrl-C lets the current rerun finish and then shows what was measured so far, a second
+        // one gives up right away
+        let stop = Arc::new(AtomicBool::new(false));
+        let handler = stop.clone();
+        let _ = ctrlc::set_handler(move || {
+            if handler.swap(true, Ordering::Relaxed) {
+                std::process::exit(error::INTERRUPTED.into());
+            }
+        });
+        let results = if opts.repeat_forever {
+            repeat::repeat_forever(&all, opts, &stop)?
+        } else {
+            benchmark_until(&all, &opts.sweep, opts.reruns, opts.split, &stop)?
+        };
+        // --repeat-forever is meant to be stopped with Ctrl-C, anything else was cut short
+        let interrupted = stop.load(Ordering::Relaxed) && !opts.repeat_forever;
+        if interrupted {
+            let reruns = results.last().map_or(0, |r| r.reruns);
+            opts.verbosity.info(&format!(
+                "PARTIAL RESULTS: interrupted after {} of {} thread counts, the last one with {reruns} of {} reruns",
+                results.len(), opts.sweep.len(), opts.reruns
+            ));
+            verdict = Err(Error::Interrupted);
+        }
+        write_bench(&format_bench(&results, opts), opts, out)?;
+        if let Some(path) = &opts.bench_json {
+            std::fs::write(path, bench_json(inputs, &results, opts)).map_err(|e| Error::write(path, e))?;
+        }
+        if let Some(path) = &opts.baseline {
+            verdict = verdict.and(baseline::check(&results, path, opts));
+        }
+        bench = Some(results);
+    }
+    // Counted once on its own, so the stats don't depend on which thread count the sweep ended with
+    let start = Instant::now();
+    let mut stats = if opts.instrumented {
+        let (stats, report) = count_chars_parallel_instrumented(&all, opts.max_threads)?;
+        let ms = |t: Duration| t.as_secs_f64() * 1000.0;
+        opts.verbosity.info(&format!(
+            "Threads had their results after {:.3} ms (first) to {:.3} ms (last), merged after {:.3} ms",
+            ms(report.spawn_latency), ms(report.completion_latency), ms(report.total)
+        ));
+        stats
+    } else {
+        count_stats(&all, opts)?
+    };
+    let elapsed = start.elapsed();
+    if opts.verify {
+        verify::check_serial(&all, &stats, opts.max_threads, opts)?;
+    }
+    let mut rng = seeded_rng(opts);
+    privatize(&mut stats, &mut rng, opts);
+    // After the noise, so that --dp-epsilon doesn't give the exact total away on stderr
+    opts.verbosity.info(&format!(
+        "Counted {} characters in {:.3} ms using {} threads",
+        fmt_num(stats.total(), group), elapsed.as_secs_f64() * 1000.0, opts.max_threads
+    ));
+
+    // The encounter order needs a serial pass of its own
+    let order = opts.first_seen.then(|| count_chars_ordered(&all).1);
+    let mut per_file = Vec::new();
+    if opts.per_file {
+        let mut combined = CharCounter::default();
+        for (path, lines) in inputs {
+            let lines = lines.strs();
+            let mut counts = count_stats(&lines, opts)?;
+            privatize(&mut counts, &mut rng, opts);
+            // JSON keeps them for the one document of the run
+            if opts.format == Format::Json {
+                per_file.push(FileCounts::new(path, &counts));
+            } else {
+                let order = opts.first_seen.then(|| count_chars_ordered(&lines).1);
+                write_report(Some(path), Some(path), lines.len(), &counts, order.as_deref(), opts, out)?;
+            }
+            combined += &counts;
+        }
+        if opts.format != Format::Json {
+            write_report(Some("combined"), None, all.len(), &combined, order.as_deref(), opts, out)?;
+        }
+        stats = combined;
+    } else if opts.format != Format::Json {
+        write_report(None, None, all.len(), &stats, order.as_deref(), opts, out)?;
+    }
+    // JSON gets all the counts of the run in one document, the per-file ones included
+    if opts.format == Format::Json {
+        let mut report = RunReport::new(input_meta(inputs, opts), &stats);
+        report.bench = bench;
+        report.per_file = per_file;
+        write_run_report(&report, out)?;
+    }
+
+    if let Some(other) = &opts.compare {
+        // Through the same loading, filtering, counting and noise as the inputs, to be comparable
+        let mut other_stats = count_stats(&load_input(other, opts)?.strs(), opts)?;
+        privatize(&mut other_stats, &mut rng, opts);
+        let (diffs, distance) = compare_freq(&stats, &other_stats, opts.threshold);
+        out.write_all(format_compare(&diffs, distance, other, group).as_bytes())?;
+    }
+
+    if let Some(name) = &opts.reference {
+        out.write_all(format_reference(&stats, &load_reference(name)?, name, group).as_bytes())?;
+    }
+
+    if opts.tiers {
+        out.write_all(format_tiers(&stats, group).as_bytes())?;
+    }
+    if opts.concentration {
+        out.write_all(format_concentration(&stats, group).as_bytes())?;
+    }
+    if opts.by_script {
+        out.write_all(format_scripts(&stats, group).as_bytes())?;
+    }
+    if opts.cooccur {
+        out.write_all(format_cooccurrences(&count_cooccurrences(&all), opts).as_bytes())?;
+    }
+    if let (Some(n), Some(&(target, _))) = (opts.top_lines, stats.top_n(1).first()) {
+        let top = top_contributing_lines_parallel(&all, target, n, opts.max_threads)?;
+        out.write_all(format_top_lines(inputs, target, &top, opts).as_bytes())?;
+    }
+
+    if let Some(path) = &opts.save_results {
+        saved::save(path, all.len(), &stats)?;
+    }
+
+    if !opts.units.is_empty() {
+        out.write_all(format_units(&count_all_units(&all, opts)?, opts).as_bytes())?;
+    }
+    verdict
+}
+
+// What --format json says about the input, the options that change the counts included
+fn input_meta(inputs: &[(&str, Input)], opts: &Options) -> InputMeta {
+    InputMeta {
+        files: inputs.iter().map(|(path, lines)| FileMeta { path: path.to_string(), bytes: lines.bytes(), lines: lines.len() }).collect(),
+        fold_case: opts.fold_case,
+        no_whitespace: opts.no_whitespace,
+        locale: opts.locale.as_ref().map(ToString::to_string),
+        range: opts.range,
+        skip_first: opts.skip_first,
+        skip_last: opts.skip_last,
+        sample: opts.sample,
+        min_len: opts.min_len,
+        max_len: (opts.max_len < usize::MAX).then_some(opts.max_len),
+        encoding: opts.encoding.map(|encoding| encoding.name().to_string()),
+        loader: if opts.per_line { "per-line" } else { "buffer" }.to_string(),
+        threads: opts.max_threads,
+        dp_epsilon: opts.dp_epsilon,
+    }
+}
+
+fn write_run_report(report: &RunReport, out: &mut dyn Write) -> Result<(), io::Error> {
+    out.write_all((serde_json::to_string_pretty(report).unwrap() + "\n").as_bytes())
+}
+
+// Reports on counts from --save-results or --load-report as if they had just been counted, a
+// report read back keeps its metadata for --format json
+fn run_saved(lines: usize, mut stats: CharCounter, report: Option<RunReport>, label: &str, opts: &Options, out: &mut dyn Write) -> Result<(), Error> {
+    if opts.no_whitespace {
+        stats.retain(|c, _| !c.is_whitespace());
+    }
+    privatize(&mut stats, &mut seeded_rng(opts), opts);
+    if opts.format == Format::Human {
+        writeln!(out, "=== {label} ===")?;
+    }
+    match report {
+        Some(report) if opts.format == Format::Json => {
+            write_run_report(&RunReport { bench: report.bench, ..RunReport::new(report.input, &stats) }, out)?;
+        }
+        _ => write_report(None, None, lines, &stats, None, opts, out)?,
+    }
+    if opts.tiers {
+        out.write_all(format_tiers(&stats, opts.group).as_bytes())?;
+    }
+    if opts.concentration {
+        out.write_all(format_concentration(&stats, opts.group).as_bytes())?;
+    }
+    if opts.by_script {
+        out.write_all(format_scripts(&stats, opts.group).as_bytes())?;
+    }
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let matches = match cli::build_cli().try_get_matches_from(cli::compat_args(std::env::args_os().collect())) {
+        Ok(matches) => matches,
+        // --help and --version aren't errors, clap prints them to stdout and exits with 0
+        Err(e) if !e.use_stderr() => e.exit(),
+        Err(e) => {
+            let _ = e.print();
+            return ExitCode::from(error::USAGE);
+        }
+    };
+    match run_command(&matches) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {e}");
+            ExitCode::from(e.exit_code())
+        }
+    }
+}
+
+fn run_command(matches: &clap::ArgMatches) -> Result<(), Error> {
+    let (name, matches) = matches.subcommand().unwrap();
+    if name == "completions" {
+        let shell = *matches.get_one::<Shell>("SHELL").unwrap();
+        clap_complete::generate(shell, &mut cli::build_cli(), env!("CARGO_BIN_NAME"), &mut io::stdout());
+        return Ok(());
+    }
+
+    let config = config::load(matches.get_one::<String>("config").map(String::as_str))?;
+    let opts = Options::from_matches(matches, &config);
+    if matches.get_flag("print-config") {
+        config::print_effective(matches, &config, &opts);
+        return Ok(());
+    }
+    if name == "serve" {
+        return Ok(serve::serve(matches.get_one::<String>("listen").map(String::as_str), &opts)?);
+    }
+    if name == "compare" {
+        let files: Vec<&str> = matches.get_many::<String>("SAVED").unwrap().map(String::as_str).collect();
+        return compare::compare([files[0], files[1]], matches.get_one::<f64>("threshold").copied(), &opts);
+    }
+    if name == "verify" {
+        return verify::verify(*matches.get_one::<usize>("lines").unwrap(), &opts);
+    }
+    let files: Vec<&str> = matches.get_many::<String>("FILE").into_iter().flatten().map(String::as_str).collect();
+    if opts.watch {
+        return watch::watch(&files, &opts, || report_on(&files, matches, &opts));
+    }
+    report_on(&files, matches, &opts)
+}
+
+// Loads the inputs, or the saved counts, and writes the report of one run
+fn report_on(files: &[&str], matches: &clap::ArgMatches, opts: &Options) -> Result<(), Error> {
+    let load = get_str(matches, "load");
+    let load_report = get_str(matches, "load-report");
+
+    let mut inputs = Vec::new();
+    let (mut skipped, mut first_error) = (0, None);
+    for &path in files {
+        let start = Instant::now();
+        let lines = match load_input(path, opts) {
+            Ok(lines) => lines,
+            // --keep-going only gets past files that can't be read, not bad options
+            Err(e @ Error::Load(LoadError::Open(..) | LoadError::Read(..))) if opts.keep_going => {
+                opts.verbosity.info(&format!("warning: {e}, skipping it"));
+                skipped += 1;
+                first_error.get_or_insert(e);
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+        // The size of mostly-ASCII text is about its character count, which --dp-epsilon hides
+        let size = if opts.dp_epsilon.is_some() { String::new() } else { format!(", {} bytes", fmt_num(lines.bytes() as u64, opts.group)) };
+        opts.verbosity.detail(&format!(
+            "Loaded {} lines{size}, from {path} in {:.3} ms",
+            fmt_num(lines.len() as u64, opts.group), start.elapsed().as_secs_f64() * 1000.0
+        ));
+        inputs.push((path, lines));
+    }
+    if let Some(e) = first_error.filter(|_| inputs.is_empty()) {
+        return Err(e);
+    }
+    if matches.get_flag("dry-run") {
+        println!("Would count {} files:", inputs.len());
+        for (path, lines) in &inputs {
+            println!(" - {path}: {} lines, {} bytes", fmt_num(lines.len() as u64, opts.group), fmt_num(lines.bytes() as u64, opts.group));
+        }
+        println!("With options: {opts:#?}");
+        return Ok(());
+    }
+
+    let label = match load.or(load_report) {
+        Some(path) => opts.title.clone().unwrap_or_else(|| path.to_string()),
+        None => title(&inputs, opts),
+    };
+    let append = matches.get_one::<String>("append-results").map(String::as_str);
+    let path = append.or(matches.get_one::<String>("output").map(String::as_str)).unwrap_or("-");
+    let mut out: Box<dyn Write> = match path {
+        _ if append.is_some() => Box::new(append_results(path, &label).map_err(|e| Error::write(path, e))?),
+        "-" => Box::new(io::stdout()),
+        _ => Box::new(io::BufWriter::new(File::create(path).map_err(|e| Error::write(path, e))?)),
+    };
+    let result = match (load, load_report) {
+        (Some(path), _) => saved::load(path).and_then(|(lines, stats)| run_saved(lines, stats, None, &label, opts, &mut out)),
+        (_, Some(path)) => saved::load_report(path).and_then(|report| {
+            let lines = report.input.files.iter().map(|file| file.lines).sum();
+            run_saved(lines, report.char_counts(), Some(report), &label, opts, &mut out)
+        }),
+        _ => run(&inputs, opts, &mut out),
+    };
+    // Anything else failing without a path of its own was writing the report
+    result.and_then(|()| Ok(out.flush()?)).map_err(|e| match e {
+        Error::Io(e) => Error::write(path, e),
+        e => e,
+    })?;
+    if skipped > 0 {
+        opts.verbosity.info(&format!("Skipped {skipped} of {} files that could not be read", files.len()));
+    }
+    Ok(())
+}
+
+fn get_str<'a>(matches: &'a clap::ArgMatches, id: &str) -> Option<&'a str> {
+    matches.try_get_one::<String>(id).ok().flatten().map(String::as_str)
+}
+
+fn title(inputs: &[(&str, Input)], opts: &Options) -> String {
+    opts.title.clone().unwrap_or_else(|| inputs.iter().map(|(path, _)| *path).collect::<Vec<_>>().join(", "))
+}
+
+// Opens the results file for one more run, a new file starts with a line saying what it holds
+fn append_results(path: &str, title: &str) -> Result<io::BufWriter<File>, io::Error> {
+    let mut file = io::BufWriter::new(OpenOptions::new().append(true).create(true).open(path)?);
+    if file.get_ref().metadata()?.len() == 0 {
+        writeln!(file, "# par_calc results, one section per run")?;
+    }
+    let time = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |t| t.as_secs());
+    writeln!(file, "# run at {time} (unix time): {title}")?;
+    Ok(file)
+}
+
@@ -0,0 +1,59 @@
+// --repeat-forever: sweeps the thread counts again and again until Ctrl-C, for long profiling runs
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use par_calc::{benchmark_until, BenchResult, TimeUnit};
+use crate::cli::Options;
+use crate::error::Error;
+use crate::fmt_time;
+
+// Welford's online mean and variance of the mean count time of one thread count, in nanoseconds
+#[derive(Clone, Copy, Default)]
+struct Running {
+    sweeps: u64,
+    mean: f64,
+    m2: f64,
+}
+
+impl Running {
+    fn add(&mut self, x: f64) {
+        self.sweeps += 1;
+        let delta = x - self.mean;
+        self.mean += delta / self.sweeps as f64;
+        self.m2 += delta * (x - self.mean);
+    }
+
+    fn stddev(&self) -> f64 {
+        if self.sweeps < 2 { 0.0 } else { (self.m2 / (self.sweeps - 1) as f64).sqrt() }
+    }
+}
+
+fn print_summary(running: &[(usize, Running)], sweeps: u64, elapsed: Duration, opts: &Options) {
+    let fastest = running.iter().filter(|(_, r)| r.sweeps > 0).map(|(_, r)| r.mean).reduce(f64::min);
+    let unit = opts.time_unit.unwrap_or_else(|| TimeUnit::fitting(fastest.unwrap_or(0.0) as u64));
+    opts.verbosity.info(&format!("Summary after {sweeps} sweeps in {:.0} s:", elapsed.as_secs_f64()));
+    for (threads, r) in running.iter().filter(|(_, r)| r.sweeps > 0) {
+        let (mean, stddev) = (fmt_time(r.mean, unit, opts.group), fmt_time(r.stddev(), unit, opts.group));
+        opts.verbosity.info(&format!(" - {threads} threads: mean {mean}, stddev {stddev} over {} sweeps", r.sweeps));
+    }
+}
+
+// Returns the last, possibly partial, sweep once `stop` is set, after a final summary
+pub fn repeat_forever(all: &[&str], opts: &Options, stop: &AtomicBool) -> Result<Vec<BenchResult>, Error> {
+    let mut running: Vec<(usize, Running)> = opts.sweep.iter().map(|&n| (n, Running::default())).collect();
+    let (start, mut last_summary, mut sweeps) = (Instant::now(), Instant::now(), 0);
+    loop {
+        let results = benchmark_until(all, &opts.sweep, opts.reruns, opts.split, stop)?;
+        sweeps += 1;
+        for (result, (_, r)) in results.iter().zip(&mut running) {
+            r.add(result.mean_nanos());
+        }
+        if stop.load(Ordering::Relaxed) {
+            print_summary(&running, sweeps, start.elapsed(), opts);
+            return Ok(results);
+        }
+        if last_summary.elapsed() >= opts.summary_interval {
+            print_summary(&running, sweeps, start.elapsed(), opts);
+            last_summary = Instant::now();
+        }
+    }
+}
@@ -0,0 +1,47 @@
+// Counts saved with --save-results, so that `stats --load` can report on them again without the input
+use std::fs;
+use serde::{Deserialize, Serialize};
+use par_calc::{CharCounter, RunReport};
+use crate::error::Error;
+use crate::{invalid_data, sorted_freq};
+
+#[derive(Serialize, Deserialize)]
+struct SavedResults {
+    lines: usize,
+    counts: Vec<SavedCount>,
+}
+
+// What --format json writes is read as saved results too
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Saved {
+    Results(SavedResults),
+    Report(Box<RunReport>),
+}
+
+#[derive(Serialize, Deserialize)]
+struct SavedCount {
+    char: char,
+    count: u64,
+}
+
+pub fn save(path: &str, lines: usize, stats: &CharCounter) -> Result<(), Error> {
+    let counts = sorted_freq(stats).into_iter().map(|(char, count)| SavedCount { char, count }).collect();
+    let json = serde_json::to_string_pretty(&SavedResults { lines, counts }).unwrap();
+    fs::write(path, json + "\n").map_err(|e| Error::write(path, e))
+}
+
+// Returns the number of lines the counts were taken from and the counts
+pub fn load(path: &str) -> Result<(usize, CharCounter), Error> {
+    let text = fs::read_to_string(path).map_err(|e| Error::open(path, e))?;
+    match serde_json::from_str(&text).map_err(|e| invalid_data(format!("{path}: {e}")))? {
+        Saved::Results(saved) => Ok((saved.lines, saved.counts.into_iter().map(|saved| (saved.char, saved.count)).collect())),
+        Saved::Report(report) => Ok((report.input.files.iter().map(|file| file.lines).sum(), report.char_counts())),
+    }
+}
+
+// A report written with --format json, for stats --load-report
+pub fn load_report(path: &str) -> Result<RunReport, Error> {
+    let text = fs::read_to_string(path).map_err(|e| Error::open(path, e))?;
+    serde_json::from_str(&text).map_err(|e| invalid_data(format!("{path}: {e}")))
+}
@@ -0,0 +1,70 @@
+// The serve subcommand: keeps counting lines as they arrive and prints the current top-N on demand
+use std::io::{self, BufRead, BufReader};
+use std::net::TcpListener;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use par_calc::{count_into, CharCounter};
+use crate::cli::Options;
+use crate::format_stats;
+
+type Shared = Arc<Mutex<CharCounter>>;
+
+fn count_lines(input: impl BufRead, counts: &Shared) -> Result<(), io::Error> {
+    for line in input.lines() {
+        count_into(&mut counts.lock().unwrap(), &line?);
+    }
+    Ok(())
+}
+
+fn print_top(counts: &Shared, opts: &Options) {
+    let freq = counts.lock().unwrap().top_n(opts.rank.unwrap_or(10));
+    print!("{}", format_stats("Most frequent characters", &freq, 0, None, opts));
+}
+
+// Counts stdin until it ends, or with `listen` the lines of every client connecting to it while
+// stdin takes commands: an empty line or "top" prints the counts, "quit" stops. On Unix SIGUSR1
+// also prints the counts.
+pub fn serve(listen: Option<&str>, opts: &Options) -> Result<(), io::Error> {
+    let counts = Shared::default();
+    thread::scope(|scope| {
+        #[cfg(unix)]
+        let signals = {
+            let mut signals = signal_hook::iterator::Signals::new([signal_hook::consts::SIGUSR1])?;
+            let handle = signals.handle();
+            let counts = &counts;
+            scope.spawn(move || signals.forever().for_each(|_| print_top(counts, opts)));
+            handle
+        };
+
+        let result = match listen {
+            None => count_lines(io::stdin().lock(), &counts),
+            Some(addr) => listen_for_clients(addr, &counts, opts),
+        };
+        #[cfg(unix)]
+        signals.close();
+        print_top(&counts, opts);
+        result
+    })
+}
+
+fn listen_for_clients(addr: &str, counts: &Shared, opts: &Options) -> Result<(), io::Error> {
+    let listener = TcpListener::bind(addr)?;
+    opts.verbosity.info(&format!("Listening on {}", listener.local_addr()?));
+    let shared = counts.clone();
+    // Never joined, the clients are simply dropped once stdin says to stop
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let counts = shared.clone();
+            thread::spawn(move || count_lines(BufReader::new(stream), &counts));
+        }
+    });
+
+    for command in io::stdin().lock().lines() {
+        match command?.trim() {
+            "" | "top" => print_top(counts, opts),
+            "quit" => break,
+            other => opts.verbosity.info(&format!("Unknown command {other:?}, expected top or quit")),
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,65 @@
+// The verify subcommand: checks on this machine that the parallel counter agrees with the serial one
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use par_calc::{count_chars, count_chars_parallel, CharCounter};
+use crate::cli::Options;
+use crate::error::Error;
+use crate::display_char;
+
+// Half ASCII so that lines share characters, half any char to get multi-byte ones, some lines empty
+fn random_text(rng: &mut StdRng, lines: usize) -> Vec<String> {
+    (0..lines)
+        .map(|_| {
+            let len = rng.gen_range(0..=80);
+            (0..len).map(|_| if rng.gen_bool(0.5) { rng.gen_range(' '..='~') } else { rng.gen::<char>() }).collect()
+        })
+        .collect()
+}
+
+// Characters whose count differs between the two, in code point order
+fn differences(expected: &CharCounter, actual: &CharCounter) -> Vec<char> {
+    let mut chars: Vec<char> = expected.keys().filter(|c| !actual.contains_key(c)).copied().collect();
+    chars.extend(actual.iter().filter(|(c, n)| expected.get(c) != Some(n)).map(|(&c, _)| c));
+    chars.sort_unstable();
+    chars
+}
+
+// --verify, a debugging aid: counts `input` again serially and lists what the parallel count of
+// `threads` got wrong
+pub fn check_serial(input: &[&str], counts: &CharCounter, threads: usize, opts: &Options) -> Result<(), Error> {
+    let expected = count_chars(input);
+    let wrong = differences(&expected, counts);
+    if wrong.is_empty() {
+        opts.verbosity.detail("The serial count agrees with the parallel one");
+        return Ok(());
+    }
+    let count = |counts: &CharCounter, c: char| counts.get(&c).copied().unwrap_or(0);
+    for c in wrong {
+        eprintln!(" - {}: serial {}, parallel {}", display_char(c), count(&expected, c), count(counts, c));
+    }
+    Err(Error::Mismatch(vec![threads]))
+}
+
+// Exits non-zero if any thread count disagrees, naming them
+pub fn verify(lines: usize, opts: &Options) -> Result<(), Error> {
+    let seed = opts.seed.unwrap_or_else(|| rand::thread_rng().gen());
+    opts.verbosity.info(&format!("Checking {lines} lines of random text, rerun with --seed {seed} to get the same text"));
+    let input = random_text(&mut StdRng::seed_from_u64(seed), lines);
+    let expected = count_chars(&input);
+
+    let mut failed = Vec::new();
+    for &n in &opts.sweep {
+        match differences(&expected, &count_chars_parallel(&input, n)?).len() {
+            0 => println!("{n} threads: ok"),
+            wrong => {
+                println!("{n} threads: MISMATCH, {wrong} characters counted differently");
+                failed.push(n);
+            }
+        }
+    }
+    if !failed.is_empty() {
+        return Err(Error::Mismatch(failed));
+    }
+    opts.verbosity.info(&format!("All {} thread counts agree with the serial count", opts.sweep.len()));
+    Ok(())
+}
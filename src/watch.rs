@@ -0,0 +1,75 @@
+// --watch: reports on the inputs again whenever they change, until Ctrl-C
+use std::fs;
+use std::io::{self, IsTerminal, Write};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use crate::cli::Options;
+use crate::error::Error;
+use crate::fmt_num;
+
+// How often the inputs are looked at
+const POLL: Duration = Duration::from_millis(100);
+
+// How long the inputs have to stay the same before counting, so that an editor saving several
+// times in a row, or deleting the file before writing the new one, starts a single run
+const DEBOUNCE: Duration = Duration::from_millis(300);
+
+// Size and modification time of every input, None while it is missing. Looking the paths up
+// again every time follows editors that save by renaming a new file over the old one, a watch on
+// the old file would never see another change.
+type Snapshot = Vec<Option<(u64, SystemTime)>>;
+
+fn snapshot(paths: &[&str]) -> Snapshot {
+    paths.iter().map(|path| fs::metadata(path).ok().map(|meta| (meta.len(), meta.modified().unwrap_or(UNIX_EPOCH)))).collect()
+}
+
+// Blocks until the inputs differ from `last` and then stop changing, returning how they ended up
+fn wait_for_change(paths: &[&str], last: &Snapshot) -> Snapshot {
+    let mut current = snapshot(paths);
+    while current == *last {
+        thread::sleep(POLL);
+        current = snapshot(paths);
+    }
+    loop {
+        thread::sleep(DEBOUNCE);
+        let settled = snapshot(paths);
+        if settled == current {
+            return settled;
+        }
+        current = settled;
+    }
+}
+
+// "12:34:56 UTC, a.txt: 1,234 bytes, b.txt: missing"
+fn stamp(paths: &[&str], snapshot: &Snapshot, opts: &Options) -> String {
+    let secs = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |t| t.as_secs()) % 86400;
+    let mut stamp = format!("{:02}:{:02}:{:02} UTC", secs / 3600, secs / 60 % 60, secs % 60);
+    for (path, file) in paths.iter().zip(snapshot) {
+        match file {
+            Some((bytes, _)) => stamp += &format!(", {path}: {} bytes", fmt_num(*bytes, opts.group)),
+            None => stamp += &format!(", {path}: missing"),
+        }
+    }
+    stamp
+}
+
+// A run that fails, e.g. on a file caught half written, is reported and the watching goes on
+pub fn watch(paths: &[&str], opts: &Options, mut report: impl FnMut() -> Result<(), Error>) -> Result<(), Error> {
+    let mut last = snapshot(paths);
+    if let Err(e) = report() {
+        eprintln!("error: {e}");
+    }
+    opts.verbosity.info(&format!("Watching {} for changes, Ctrl-C to stop", paths.join(", ")));
+    loop {
+        last = wait_for_change(paths, &last);
+        // A terminal gets a fresh screen per report, anything else the reports one after another
+        if io::stdout().is_terminal() {
+            print!("\x1b[2J\x1b[H");
+            io::stdout().flush()?;
+        }
+        opts.verbosity.info(&format!("--- Changed at {} ---", stamp(paths, &last, opts)));
+        if let Err(e) = report() {
+            eprintln!("error: {e}");
+        }
+    }
+}
@@ -0,0 +1,998 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+fn fixture(name: &str, content: impl AsRef<[u8]>) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("par_calc_{}_{name}", std::process::id()));
+    std::fs::write(&path, content).unwrap();
+    path
+}
+
+fn par_calc(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_par_calc")).args(args).output().unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    String::from_utf8(output.stdout.clone()).unwrap()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8(output.stderr.clone()).unwrap()
+}
+
+#[test]
+fn count_reports_once() {
+    let file = fixture("count.txt", "aab\nb\n");
+    let output = par_calc(&["count", file.to_str().unwrap(), "--threads", "2", "--stats", "1"]);
+    let report = format!("=== {} ===\nTotal lines: 2, Total characters: 4\nMost frequent characters:\n - 'a': 2 occurrences\n", file.display());
+    assert_eq!(stdout(&output), report);
+    let timing = stderr(&output);
+    assert!(timing.starts_with("Counted 4 characters in ") && timing.ends_with(" ms using 2 threads\n"));
+}
+
+#[test]
+fn equal_counts_are_listed_by_code_point() {
+    let file = fixture("ties.txt", "zyx\ncab\nqz\nq\n");
+    for threads in ["1", "3"] {
+        let out = stdout(&par_calc(&["count", file.to_str().unwrap(), "--threads", threads, "--stats", "6", "-q"]));
+        assert_eq!(out, format!(
+            "=== {} ===\nTotal lines: 4, Total characters: 9\nMost frequent characters:\n - 'q': 2 occurrences\n - 'z': 2 occurrences\n \
+             - 'a': 1 occurrences\n - 'b': 1 occurrences\n - 'c': 1 occurrences\n - 'x': 1 occurrences\n",
+            file.display()
+        ), "{threads} threads");
+    }
+    // The machine-readable listings are in the same order
+    let out = stdout(&par_calc(&["count", file.to_str().unwrap(), "--stats", "4", "-q", "--format", "csv"]));
+    assert_eq!(out, "char,count\n\"q\",2\n\"z\",2\n\"a\",1\n\"b\",1\n");
+    let out = stdout(&par_calc(&["count", file.to_str().unwrap(), "--stats", "4", "-q", "--format", "jsonl"]));
+    assert_eq!(out, "{\"char\":\"q\",\"count\":2}\n{\"char\":\"z\",\"count\":2}\n{\"char\":\"a\",\"count\":1}\n{\"char\":\"b\",\"count\":1}\n");
+}
+
+#[test]
+fn bench_sweeps_thread_counts() {
+    let file = fixture("bench.txt", "abc\n");
+    let output = par_calc(&["bench", file.to_str().unwrap(), "--max", "2", "--reruns", "1"]);
+    assert!(output.status.success());
+    let out = stdout(&output);
+    assert!(out.contains("Average time with 1 threads"));
+    assert!(out.contains("Average time with 2 threads"));
+}
+
+#[test]
+fn verbose_bench_shows_the_chunk_size_per_thread_count() {
+    let file = fixture("bench_chunks.txt", "abc\n".repeat(100_000));
+    let output = par_calc(&["bench", file.to_str().unwrap(), "--max", "4", "--reruns", "1", "--chunk-lines", "auto", "-v"]);
+    assert!(output.status.success());
+    let out = stderr(&output);
+    for line in ["Benchmarking 1 threads with 8 chunks of 12500 lines", "Benchmarking 4 threads with 32 chunks of 3125 lines"] {
+        assert!(out.contains(line), "{out}");
+    }
+}
+
+#[test]
+fn bench_times_share_the_requested_unit() {
+    let file = fixture("bench_unit.txt", "abc\n");
+    let output = par_calc(&["bench", file.to_str().unwrap(), "--max", "2", "--reruns", "1", "--time-unit", "us"]);
+    let out = stdout(&output);
+    let times: Vec<&str> = out.lines().filter(|line| line.starts_with("Average time")).collect();
+    assert_eq!(times.len(), 2);
+    for line in times {
+        let time = line.strip_suffix(" µs").unwrap_or_else(|| panic!("{line}"));
+        assert_eq!(time.split_once('.').unwrap().1.len(), 3, "{line}");
+    }
+}
+
+#[test]
+fn percentiles_warn_about_few_reruns() {
+    let file = fixture("bench_pct.txt", "abc\n");
+    let output = par_calc(&["bench", file.to_str().unwrap(), "--threads-fixed", "1", "--reruns", "10", "--percentile-benchmark"]);
+    let err = stderr(&output);
+    assert!(err.contains("warning: with only 10 reruns"), "{err}");
+    let out = stdout(&output);
+    assert!(out.contains(", p50 ") && out.contains(", p95 ") && out.contains(", p99 "), "{out}");
+}
+
+#[test]
+fn tiny_inputs_have_a_non_zero_mean() {
+    let file = fixture("bench_tiny.txt", "a\n");
+    let output = par_calc(&["bench", file.to_str().unwrap(), "--threads-fixed", "1", "--reruns", "10000", "--time-unit", "ns"]);
+    let out = stdout(&output);
+    let mean = out.lines().find_map(|line| line.strip_prefix("Average time with 1 threads: ")).unwrap();
+    assert!(mean.trim().strip_suffix(" ns").unwrap().parse::<f64>().unwrap() > 0.0, "{out}");
+}
+
+// Runs par_calc and sends it SIGINT after `millis`
+#[cfg(unix)]
+fn interrupted(args: &[&str], millis: u64) -> Output {
+    let child = Command::new(env!("CARGO_BIN_EXE_par_calc"))
+        .args(args)
+        .stderr(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(millis));
+    Command::new("kill").args(["-INT", &child.id().to_string()]).status().unwrap();
+    child.wait_with_output().unwrap()
+}
+
+#[cfg(unix)]
+#[test]
+fn repeat_forever_summarizes_on_ctrl_c() {
+    let file = fixture("forever.txt", "abc\n");
+    let output = interrupted(&["bench", file.to_str().unwrap(), "--max", "2", "--reruns", "1", "--repeat-forever", "--summary-interval", "1"], 1500);
+    let out = stderr(&output);
+    assert!(output.status.success(), "{out}");
+    assert!(out.contains("Summary after ") && out.contains(" - 2 threads: mean "), "{out}");
+}
+
+#[cfg(unix)]
+#[test]
+fn ctrl_c_reports_the_partial_sweep() {
+    let file = fixture("interrupted.txt", "abc\n");
+    let output = interrupted(&["bench", file.to_str().unwrap(), "--max", "4", "--reruns", "4000000000", "--stats", "1"], 500);
+    let out = stderr(&output);
+    assert_eq!(output.status.code(), Some(130), "{out}");
+    assert!(out.contains("PARTIAL RESULTS: interrupted after 1 of 4 thread counts, the last one with "), "{out}");
+    assert!(out.ends_with("error: interrupted, the benchmark results are partial\n"), "{out}");
+    let out = String::from_utf8_lossy(&output.stdout);
+    assert!(out.contains("Average time with 1 threads: ") && out.ends_with(" - 'a': 1 occurrences\n"), "{out}");
+}
+
+#[test]
+fn bench_runs_only_the_listed_thread_counts() {
+    let file = fixture("bench_csv.txt", "abc\n");
+    let output = par_calc(&["bench", file.to_str().unwrap(), "--threads-csv", "1,3", "--reruns", "1"]);
+    assert!(output.status.success());
+    let out = stdout(&output);
+    assert!(out.contains("Average time with 1 threads") && out.contains("Average time with 3 threads"));
+    assert!(!out.contains("Average time with 2 threads"));
+}
+
+#[test]
+fn collate_codepoint_sorts_by_scalar_value() {
+    let file = fixture("collate.txt", "éfa\n");
+    let sorted = |collate: &[&str]| {
+        let out = stdout(&par_calc(&[&["count", file.to_str().unwrap(), "-q", "--no-summary", "--sort-by", "char-asc", "-f", "csv"], collate].concat()));
+        out.lines().skip(1).map(|line| line.chars().nth(1).unwrap()).collect::<String>()
+    };
+    assert_eq!(sorted(&["--collate", "alphabetic"]), "aéf");
+    assert_eq!(sorted(&["--collate", "codepoint"]), "afé");
+    // Collation is opt-in
+    assert_eq!(sorted(&[]), "afé");
+}
+
+#[test]
+fn zero_pad_lines_up_the_counts() {
+    let file = fixture("zero_pad.txt", format!("{}\u{1}b\n", "a".repeat(1200)));
+    let listing = |extra: &str| {
+        let out = stdout(&par_calc(&["count", file.to_str().unwrap(), "-q", "--no-summary", "-s", "3", "--zero-pad", extra]));
+        out.lines().skip(2).map(str::to_string).collect::<Vec<_>>()
+    };
+    let padded = listing("--color=never");
+    assert_eq!(padded[0], " - 'a':                 1200 occurrences");
+    assert_eq!(padded[1], " - '\\u{0001}' (U+0001): 0001 occurrences");
+    assert_eq!(listing("--format-number")[2], " - 'b':                     1 occurrences");
+}
+
+#[test]
+fn verify_checks_every_thread_count() {
+    let output = par_calc(&["verify", "--max", "3", "--lines", "50", "--seed", "1"]);
+    assert_eq!(stdout(&output), "1 threads: ok\n2 threads: ok\n3 threads: ok\n");
+    assert!(stderr(&output).contains("--seed 1"));
+}
+
+#[test]
+fn stats_lists_top_and_bottom() {
+    let file = fixture("stats.txt", "aaabbc\n");
+    let out = stdout(&par_calc(&["stats", file.to_str().unwrap(), "--top", "1", "--bottom", "1", "--no-summary"]));
+    assert_eq!(out.split_once('\n').unwrap().1, "Most frequent characters:\n - 'a': 3 occurrences\nLeast frequent characters:\n - 'c': 1 occurrences\n");
+}
+
+#[test]
+fn stats_rank_below_and_at_the_distinct_count_is_not_noted() {
+    let file = fixture("rank_fits.txt", "aaabbc\n");
+    for rank in [2, 3] {
+        let out = stdout(&par_calc(&["count", file.to_str().unwrap(), "--stats", &rank.to_string(), "--no-summary", "--pad-missing"]));
+        assert_eq!(out.lines().filter(|line| line.starts_with(" - ")).count(), rank);
+        assert!(!out.contains("(only") && !out.contains("(missing)"), "{out}");
+    }
+}
+
+#[test]
+fn stats_rank_past_the_distinct_count_is_noted_and_padded() {
+    let file = fixture("rank_short.txt", "aaabbc\n");
+    let out = stdout(&par_calc(&["count", file.to_str().unwrap(), "--stats", "5", "--no-summary"]));
+    assert!(out.ends_with(" - 'c': 1 occurrences\n(only 3 distinct characters present)\n"), "{out}");
+    let out = stdout(&par_calc(&["count", file.to_str().unwrap(), "--stats", "5", "--no-summary", "--pad-missing", "--format", "csv"]));
+    assert_eq!(out, "char,count\n\"a\",3\n\"b\",2\n\"c\",1\n,0\n,0\n");
+}
+
+#[test]
+fn old_invocation_without_bench_flags_counts_once() {
+    let file = fixture("compat_count.txt", "abc\n");
+    let output = par_calc(&[file.to_str().unwrap(), "--stats", "1"]);
+    assert!(output.status.success());
+    let out = stderr(&output);
+    assert!(out.contains("\nCounted 3 characters in "));
+    assert!(!out.contains("Average time"));
+}
+
+#[test]
+fn bench_counts_the_stats_once_after_the_sweep() {
+    let file = fixture("bench_stats.txt", "aab\nb\nc\n");
+    let output = par_calc(&["bench", file.to_str().unwrap(), "--max", "3", "--reruns", "2", "--stats", "2", "-q"]);
+    let count = par_calc(&["count", file.to_str().unwrap(), "--stats", "2", "-q"]);
+    let (out, count) = (stdout(&output), stdout(&count));
+    assert!(out.contains("Average time with 3 threads: ") && out.ends_with(count.split_once('\n').unwrap().1), "{out}");
+    assert_eq!(stderr(&par_calc(&["bench", file.to_str().unwrap(), "--max", "3", "--reruns", "2"])).lines().filter(|line| line.starts_with("Counted 5 characters in ")).count(), 1);
+}
+
+#[test]
+fn instrumented_reports_when_the_threads_finished() {
+    let file = fixture("instrumented.txt", "ab\nbc\ncd\n");
+    let output = par_calc(&["count", file.to_str().unwrap(), "--threads", "3", "--instrumented"]);
+    let out = stderr(&output);
+    assert!(output.status.success());
+    assert!(out.starts_with("Threads had their results after ") && out.contains(" ms (last), merged after "), "{out}");
+}
+
+#[test]
+fn every_engine_gives_the_same_stats() {
+    let file = fixture("engines.txt", "aab\nbc\n𝄞c\n");
+    let parallel = stdout(&par_calc(&["count", file.to_str().unwrap(), "-q", "--threads", "2"]));
+    let serial = stdout(&par_calc(&["count", file.to_str().unwrap(), "-q", "--threads", "2", "--engine", "serial"]));
+    assert_eq!(serial, parallel);
+    assert_eq!(par_calc(&["count", file.to_str().unwrap(), "--engine", "rayon"]).status.code(), Some(64));
+}
+
+#[test]
+fn keep_going_skips_unreadable_files() {
+    let file = fixture("keep_going.txt", "aab\n");
+    let missing = std::env::temp_dir().join("par_calc_no_such_file.txt");
+    let args = ["count", missing.to_str().unwrap(), file.to_str().unwrap(), "--stats", "1"];
+    let output = par_calc(&args);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stderr(&output).contains("par_calc_no_such_file.txt"));
+
+    let output = par_calc(&[&args[..], &["--keep-going"]].concat());
+    assert!(stdout(&output).ends_with(" - 'a': 2 occurrences\n"));
+    let out = stderr(&output);
+    assert!(out.starts_with("warning: cannot open '") && out.ends_with("Skipped 1 of 2 files that could not be read\n"), "{out}");
+    assert_eq!(par_calc(&["count", missing.to_str().unwrap(), "--keep-going"]).status.code(), Some(2));
+}
+
+#[test]
+fn verify_flag_checks_against_the_serial_count() {
+    let file = fixture("verify_flag.txt", "aab\nbé\n\ncb\n");
+    let output = par_calc(&["count", file.to_str().unwrap(), "--threads", "3", "--verify", "--verbose", "--stats", "1"]);
+    assert!(stdout(&output).ends_with(" - 'b': 3 occurrences\n"));
+    assert!(stderr(&output).contains("The serial count agrees with the parallel one\n"));
+}
+
+#[test]
+fn both_loaders_count_the_same() {
+    let file = fixture("loaders.txt", "ab\r\n\nçb\nlast");
+    let counts = ["buffer", "per-line"].map(|loader| {
+        stdout(&par_calc(&["count", file.to_str().unwrap(), "--loader", loader, "--line-range", "2:4", "--fold-case", "-q", "-s", "all"]))
+    });
+    assert_eq!(counts[0], counts[1]);
+    assert!(counts[0].contains("Total lines: 3, Total characters: 6\n"), "{}", counts[0]);
+}
+
+#[test]
+fn print_codepoints_names_the_characters() {
+    let file = fixture("codepoints.txt", "a -\t\n");
+    let out = stdout(&par_calc(&["count", file.to_str().unwrap(), "-q", "-s", "all", "--print-codepoints", "--no-summary"]));
+    let lines: Vec<&str> = out.lines().skip(2).collect();
+    assert_eq!(lines, [
+        " - U+0009 '\\t': 1 occurrences",
+        " - U+0020 ' ' (SPACE): 1 occurrences",
+        " - U+002D '-' (HYPHEN-MINUS): 1 occurrences",
+        " - U+0061 'a' (LATIN SMALL LETTER A): 1 occurrences",
+    ]);
+}
+
+#[test]
+fn baseline_fails_on_a_slowdown() {
+    let file = fixture("baseline_input.txt", "abc\nde\n");
+    let fast = fixture("baseline_fast.json", r#"{"results": [{"threads": 1, "mean_ns": 0.5}, {"threads": 2, "mean_ns": 1e12}]}"#);
+    let output = par_calc(&["bench", file.to_str().unwrap(), "--max", "3", "--reruns", "1", "--baseline", fast.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(1));
+    let out = stderr(&output);
+    assert!(out.contains("%), regression\n - 2 threads: ") && out.contains(" - 3 threads: not in the baseline\n"), "{out}");
+    assert!(out.ends_with("error: more than 5% slower than the baseline with 1 threads\n"), "{out}");
+
+    let slow = fixture("baseline_slow.json", r#"{"results": [{"threads": 1, "mean_ns": 1e12}]}"#);
+    let output = par_calc(&["bench", file.to_str().unwrap(), "--max", "1", "--reruns", "1", "--baseline", slow.to_str().unwrap()]);
+    assert!(output.status.success(), "{}", stderr(&output));
+}
+
+#[test]
+fn compare_strategies_times_each_engine() {
+    let file = fixture("strategies.txt", "abc\nde\n");
+    let output = par_calc(&["bench", file.to_str().unwrap(), "-m", "3", "-r", "2", "--compare-strategies"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let out = stdout(&output).split_once('\n').unwrap().1.to_string();
+    assert!(out.starts_with("Average time per engine with 3 threads:\n - serial:   "), "{out}");
+    assert!(out.contains("\n - parallel: ") && out.contains("x the fastest)\n") && !out.contains("Average time with"), "{out}");
+}
+
+#[test]
+fn by_script_totals_each_writing_system() {
+    let file = fixture("scripts.txt", "hello мир\n世界\n");
+    let out = stdout(&par_calc(&["count", file.to_str().unwrap(), "-q", "--no-summary", "--by-script"]));
+    assert!(out.ends_with("Characters per script:\n - Latin: 5 (45.45%)\n - Cyrillic: 3 (27.27%)\n - Han: 2 (18.18%)\n - Common: 1 (9.09%)\n"), "{out}");
+}
+
+#[test]
+fn dp_epsilon_adds_noise_to_the_counts() {
+    let file = fixture("dp.txt", "the quick brown fox jumps over the lazy dog\n");
+    let args = ["count", file.to_str().unwrap(), "-q", "-s", "all", "--format", "csv"];
+    let exact = stdout(&par_calc(&args));
+    let noisy = stdout(&par_calc(&[&args[..], &["--dp-epsilon", "0.0001", "--seed", "3"]].concat()));
+    assert_ne!(noisy, exact);
+    assert!(noisy.lines().skip(1).all(|line| line.rsplit_once(',').unwrap().1.parse::<u64>().unwrap() > 0), "{noisy}");
+    assert_eq!(stdout(&par_calc(&[&args[..], &["--dp-epsilon", "0.0001", "--seed", "3"]].concat())), noisy);
+}
+
+#[test]
+fn dp_epsilon_lets_no_exact_count_out() {
+    let file = fixture("dp_leak.txt", format!("{}\n{}\n", "a".repeat(1237), "b".repeat(2411)));
+    let output = par_calc(&["count", file.to_str().unwrap(), "-v", "--dp-epsilon", "0.0001", "--seed", "5", "--tiers", "--concentration", "--by-script"]);
+    assert!(output.status.success());
+    let all = stdout(&output) + &stderr(&output);
+    for exact in ["1237", "2411", "3648", "1,237", "2,411", "3,648"] {
+        assert!(!all.contains(exact), "{exact} in {all}");
+    }
+    for other in [&["--cooccur"][..], &["--top-lines", "1"], &["--unit", "char"], &["--verify"]] {
+        let output = par_calc(&[&["count", file.to_str().unwrap(), "--dp-epsilon", "1"], other].concat());
+        assert_eq!(output.status.code(), Some(64), "{other:?}");
+        assert!(stderr(&output).contains("cannot be used with"), "{other:?}: {}", stderr(&output));
+    }
+    assert_eq!(par_calc(&["bench", file.to_str().unwrap(), "--dp-epsilon", "1", "--imbalance"]).status.code(), Some(64));
+}
+
+#[test]
+fn backwards_or_zero_line_ranges_are_rejected() {
+    rejected(&["count", "--line-range", "5:3"], "invalid value '5:3' for '--line-range <RANGE>': start 5 is greater than end 3");
+    rejected(&["count", "--line-range", "0:3"], "invalid value '0:3' for '--line-range <RANGE>': line numbers start at 1");
+    rejected(&["count", "--line-range", "3"], "invalid value '3' for '--line-range <RANGE>': expected START:END");
+}
+
+#[test]
+fn zero_dp_epsilon_is_rejected() {
+    rejected(&["count", "--dp-epsilon", "0"], "invalid value '0' for '--dp-epsilon <EPSILON>': must be a positive number");
+}
+
+#[test]
+fn line_endings_are_counted_by_kind() {
+    let file = fixture("endings.txt", b"dos\r\nunix\nold mac\rdos\r\n\r\nlast");
+    for loader in ["buffer", "per-line"] {
+        let output = par_calc(&["count", file.to_str().unwrap(), "--line-endings", "--loader", loader, "--line-range", "1:2"]);
+        let out = stderr(&output);
+        assert!(out.starts_with(&format!("Line endings of {}: 1 LF, 3 CRLF, 1 CR, mixed\n", file.display())), "{out}");
+    }
+}
+
+#[test]
+fn dry_run_shows_lines_and_bytes() {
+    let file = fixture("sizes.txt", "née\r\nab\n\n");
+    for loader in ["buffer", "per-line"] {
+        let out = stdout(&par_calc(&["count", file.to_str().unwrap(), "--dry-run", "--loader", loader]));
+        assert!(out.contains(&format!(" - {}: 3 lines, 6 bytes\n", file.display())), "{out}");
+    }
+}
+
+#[test]
+fn old_invocation_runs_bench() {
+    let file = fixture("compat.txt", "abc\n");
+    let output = par_calc(&[file.to_str().unwrap(), "-m", "1", "-r", "1"]);
+    assert!(output.status.success());
+    assert!(stdout(&output).contains("Average time with 1 threads"));
+    assert!(stderr(&output).contains("deprecated"));
+}
+
+#[test]
+fn output_writes_stats_to_file() {
+    let file = fixture("output.txt", "aab\n");
+    let json = std::env::temp_dir().join(format!("par_calc_{}_output.json", std::process::id()));
+    let output = par_calc(&["count", file.to_str().unwrap(), "--format", "json", "--output", json.to_str().unwrap()]);
+    assert_eq!(stdout(&output), "");
+    let report: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&json).unwrap()).unwrap();
+    assert_eq!(report["counts"], serde_json::json!({"a": 2, "b": 1}));
+    assert_eq!(report["input"]["files"], serde_json::json!([{"path": file.to_str().unwrap(), "bytes": 3, "lines": 1}]));
+    assert_eq!((&report["stats"]["total_chars"], &report["stats"]["distinct"], &report["bench"]), (&3.into(), &2.into(), &serde_json::Value::Null));
+}
+
+#[test]
+fn output_gets_the_compare_and_reference_sections_too() {
+    let (file, other) = (fixture("output_compare.txt", "aab\n"), fixture("output_compare_other.txt", "bc\n"));
+    let path = std::env::temp_dir().join(format!("par_calc_{}_output_compare.txt", std::process::id()));
+    let output = par_calc(&["count", file.to_str().unwrap(), "--compare", other.to_str().unwrap(), "--reference", "english", "-o", path.to_str().unwrap()]);
+    assert!(output.status.success());
+    assert_eq!(stdout(&output), "");
+    let report = std::fs::read_to_string(&path).unwrap();
+    assert!(report.contains("Total variation distance: 0.666667\n"), "{report}");
+    assert!(report.contains("Comparison against reference english:\n"), "{report}");
+}
+
+#[test]
+fn compare_file_goes_through_the_same_filters() {
+    let (file, other) = (fixture("compare_filters.txt", "aA b\nskip\n"), fixture("compare_filters_other.txt", "AA\tB\nskip me\n"));
+    let args = ["count", file.to_str().unwrap(), "--compare", other.to_str().unwrap(), "-q", "--fold-case", "--no-whitespace", "--line-range", "1:1"];
+    let out = stdout(&par_calc(&args));
+    assert!(out.contains("Total variation distance: 0.000000\n"), "{out}");
+}
+
+#[test]
+fn json_report_can_be_loaded_back() {
+    let file = fixture("report.txt", "aa𝄞\nb\n");
+    let path = file.to_str().unwrap();
+    let report = std::env::temp_dir().join(format!("par_calc_{}_report.json", std::process::id()));
+    let output = par_calc(&["bench", path, "-m", "2", "-r", "1", "--fold-case", "-f", "json", "-o", report.to_str().unwrap()]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let json: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&report).unwrap()).unwrap();
+    assert_eq!((&json["input"]["fold_case"], json["bench"].as_array().map(Vec::len)), (&true.into(), Some(2)));
+
+    let report = report.to_str().unwrap();
+    let out = stdout(&par_calc(&["stats", "--load-report", report, "--top", "2"]));
+    assert_eq!(out, format!("=== {report} ===\nTotal lines: 2, Total characters: 4\nMost frequent characters:\n - 'a': 2 occurrences\n - 'b': 1 occurrences\n"));
+    let again = stdout(&par_calc(&["stats", "--load-report", report, "-f", "json"]));
+    let again: serde_json::Value = serde_json::from_str(&again).unwrap();
+    assert_eq!((&again["input"], &again["counts"], &again["bench"]), (&json["input"], &json["counts"], &json["bench"]));
+    // As the counts of compare and the timings of --baseline
+    assert!(stdout(&par_calc(&["compare", report, report])).contains("Cosine similarity: 1.000000\n"));
+    let output = par_calc(&["bench", path, "-m", "2", "-r", "1", "--baseline", report, "--regression-threshold", "1e9"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+}
+
+#[test]
+fn per_file_json_is_one_document() {
+    let (a, b) = (fixture("per_file_a.json.txt", "aab\n"), fixture("per_file_b.json.txt", "bc\n"));
+    let (a, b) = (a.to_str().unwrap(), b.to_str().unwrap());
+    let out = stdout(&par_calc(&["count", a, b, "--per-file", "-f", "json", "-q"]));
+    let report: serde_json::Value = serde_json::from_str(&out).unwrap();
+    assert_eq!(report["counts"], serde_json::json!({"a": 2, "b": 2, "c": 1}));
+    let files: Vec<_> = report["per_file"].as_array().unwrap().iter().map(|file| (file["path"].clone(), file["counts"].clone())).collect();
+    assert_eq!(files, [(a.into(), serde_json::json!({"a": 2, "b": 1})), (b.into(), serde_json::json!({"b": 1, "c": 1}))]);
+}
+
+#[test]
+fn per_file_csv_and_jsonl_rows_name_their_file() {
+    let (a, b) = (fixture("per_file_a.rows.txt", "aab\n"), fixture("per_file_b.rows.txt", "bc\n"));
+    let (a, b) = (a.to_str().unwrap(), b.to_str().unwrap());
+    // Counts per file, "" for the combined section, from records of (file, char, count)
+    let sections = |records: Vec<(String, String, u64)>| {
+        let mut sections: BTreeMap<String, BTreeMap<String, u64>> = BTreeMap::new();
+        for (file, c, n) in records {
+            *sections.entry(file).or_default().entry(c).or_default() += n;
+        }
+        sections
+    };
+    let csv = stdout(&par_calc(&["count", a, b, "--per-file", "-f", "csv", "-q"]));
+    let csv = sections(csv.lines().filter(|line| *line != "file,char,count").map(|line| {
+        let fields: Vec<&str> = line.split(',').collect();
+        (fields[0].trim_matches('"').to_string(), fields[1].to_string(), fields[2].parse().unwrap())
+    }).collect());
+    let jsonl = stdout(&par_calc(&["count", a, b, "--per-file", "-f", "jsonl", "-q"]));
+    let jsonl = sections(jsonl.lines().map(|line| {
+        let record: serde_json::Value = serde_json::from_str(line).unwrap();
+        (record["file"].as_str().unwrap_or("").to_string(), record["char"].as_str().unwrap().to_string(), record["count"].as_u64().unwrap())
+    }).collect());
+    for sections in [csv, jsonl] {
+        assert_eq!(sections.keys().collect::<Vec<_>>(), ["", a, b]);
+        let mut sum = BTreeMap::new();
+        for (c, n) in sections[a].iter().chain(&sections[b]) {
+            *sum.entry(c.clone()).or_default() += n;
+        }
+        assert_eq!(sections[""], sum);
+    }
+}
+
+#[test]
+fn human_output_spells_out_invisible_characters() {
+    // Saved counts, as no counted line has a newline in it
+    let counts: Vec<_> = [('\n', 6), ('\t', 5), ('\u{a0}', 4), ('\u{301}', 3), ('\u{1F600}', 2), ('e', 1)]
+        .iter().map(|(c, n)| serde_json::json!({"char": c.to_string(), "count": n})).collect();
+    let saved = std::env::temp_dir().join(format!("par_calc_{}_display_char.json", std::process::id()));
+    std::fs::write(&saved, serde_json::json!({"lines": 1, "counts": counts}).to_string()).unwrap();
+    let out = stdout(&par_calc(&["stats", "--load", saved.to_str().unwrap(), "-q", "--top", "6"]));
+    assert!(out.ends_with(concat!(
+        "Most frequent characters:\n",
+        " - '\\n' (U+000A): 6 occurrences\n",
+        " - '\\t' (U+0009): 5 occurrences\n",
+        " - '\\u{00A0}' NBSP: 4 occurrences\n",
+        " - '\\u{0301}' (U+0301): 3 occurrences\n",
+        " - '😀': 2 occurrences\n",
+        " - 'e': 1 occurrences\n",
+    )), "{out}");
+    // CSV has the characters themselves, only quoted
+    let out = stdout(&par_calc(&["stats", "--load", saved.to_str().unwrap(), "-q", "--top", "6", "-f", "csv"]));
+    assert_eq!(out, "char,count\n\"\n\",6\n\"\t\",5\n\"\u{a0}\",4\n\"\u{301}\",3\n\"\u{1F600}\",2\n\"e\",1\n");
+}
+
+#[test]
+fn tsv_lists_code_point_char_and_count() {
+    let file = fixture("tsv.txt", "a\tb\na𝄞\n");
+    let output = par_calc(&["bench", file.to_str().unwrap(), "-m", "2", "-r", "2", "--tsv", "--stats", "3", "--pad-missing"]);
+    assert_eq!(stdout(&output), "97\ta\t2\n9\t<U+0009>\t1\n98\tb\t1\n");
+    let err = stderr(&output);
+    let timings: Vec<Vec<&str>> = err.lines().take(2).map(|line| line.split('\t').collect()).collect();
+    assert_eq!(timings.iter().map(|row| row[0]).collect::<Vec<_>>(), ["1", "2"]);
+    assert!(timings.iter().all(|row| row.len() == 2 && row[1].parse::<u64>().is_ok()), "{err}");
+    let padded = stdout(&par_calc(&["count", file.to_str().unwrap(), "-q", "-f", "tsv", "--stats", "6", "--pad-missing"]));
+    assert!(padded.ends_with("119070\t𝄞\t1\n\t\t0\n\t\t0\n"), "{padded}");
+}
+
+#[test]
+fn bench_count_only_skips_the_sweep() {
+    let file = fixture("count_only.txt", "abc\n");
+    let output = par_calc(&["bench", file.to_str().unwrap(), "--count-only"]);
+    assert!(output.status.success());
+    let out = stderr(&output);
+    assert!(out.starts_with("Counted 3 characters in "));
+    assert!(!out.contains("Average time"));
+}
+
+#[test]
+fn quiet_csv_prints_only_the_table() {
+    let file = fixture("quiet.txt", "aab\n");
+    let output = par_calc(&["bench", file.to_str().unwrap(), "--quiet", "--format", "csv", "-m", "2", "-r", "1"]);
+    assert_eq!(stdout(&output), "char,count\n\"a\",2\n\"b\",1\n");
+    // The timings are the result of bench, kept apart from the table
+    let err = stderr(&output);
+    assert!(err.lines().map(|line| line.split_once(':').unwrap().0).eq(["Average time with 1 threads", "Average time with 2 threads"]), "{err}");
+}
+
+#[test]
+fn quiet_bench_keeps_the_timings() {
+    let file = fixture("quiet_bench.txt", "aab\n");
+    let output = par_calc(&["bench", file.to_str().unwrap(), "--quiet", "-m", "2", "-r", "1", "--stats", "1"]);
+    let out = stdout(&output);
+    let lines: Vec<&str> = out.lines().collect();
+    assert_eq!(lines.len(), 6, "{out}");
+    assert!(lines[1].starts_with("Average time with 1 threads: ") && lines[2].starts_with("Average time with 2 threads: "), "{out}");
+    assert_eq!(lines[3..], ["Total lines: 1, Total characters: 3", "Most frequent characters:", " - 'a': 2 occurrences"]);
+    assert_eq!(stderr(&output), "");
+    let tsv = par_calc(&["bench", file.to_str().unwrap(), "--quiet", "--tsv", "-m", "2", "-r", "1", "--stats", "1"]);
+    assert_eq!(stdout(&tsv), "97\ta\t2\n");
+    assert!(stderr(&tsv).lines().map(|line| line.split_once('\t').unwrap().0).eq(["1", "2"]), "{}", stderr(&tsv));
+}
+
+fn rejected(args: &[&str], message: &str) {
+    let file = fixture("invalid.txt", "abc\n");
+    let mut full = vec![args[0], file.to_str().unwrap()];
+    full.extend(&args[1..]);
+    let output = par_calc(&full);
+    assert_eq!(output.status.code(), Some(64));
+    assert!(stderr(&output).contains(message), "{}", stderr(&output));
+}
+
+#[test]
+fn zero_max_is_rejected() {
+    rejected(&["bench", "--max", "0"], "invalid value '0' for '--max <MAX>': 0 is not in 1..=1024");
+}
+
+#[test]
+fn huge_max_is_rejected() {
+    rejected(&["bench", "--max", "5000"], "invalid value '5000' for '--max <MAX>': 5000 is not in 1..=1024");
+}
+
+#[test]
+fn zero_in_threads_csv_is_rejected() {
+    rejected(&["bench", "--threads-csv", "1,0"], "invalid value '0' for '--threads-csv <LIST>'");
+}
+
+#[test]
+fn zero_threads_is_rejected() {
+    rejected(&["count", "--threads", "0"], "invalid value '0' for '--threads <N>'");
+}
+
+#[test]
+fn zero_reruns_is_rejected() {
+    rejected(&["bench", "--reruns", "0"], "invalid value '0' for '--reruns <RERUNS>': must be at least 1");
+}
+
+#[test]
+fn zero_stats_is_rejected() {
+    rejected(&["count", "--stats", "0"], "invalid value '0' for '--stats <rank>': must be at least 1");
+}
+
+#[test]
+fn zero_top_is_rejected() {
+    rejected(&["stats", "--top", "0"], "invalid value '0' for '--top <N>': must be at least 1");
+}
+
+#[test]
+fn encoding_decodes_utf16_and_latin1() {
+    let utf16: Vec<u8> = "\u{feff}née\r\nô\r\n".encode_utf16().flat_map(u16::to_le_bytes).collect();
+    let file = fixture("utf16le.txt", utf16);
+    let out = stdout(&par_calc(&["count", file.to_str().unwrap(), "-q", "--encoding", "utf16le", "-s", "all"]));
+    assert!(out.contains("Total lines: 2, Total characters: 4\n") && out.contains(" - 'ô': 1 occurrences\n"), "{out}");
+    let file = fixture("latin1.txt", b"caf\xe9\n");
+    let out = stdout(&par_calc(&["count", file.to_str().unwrap(), "-q", "--encoding", "latin1", "-s", "all"]));
+    assert!(out.contains(" - 'é': 1 occurrences\n"), "{out}");
+    let output = par_calc(&["count", file.to_str().unwrap(), "--encoding", "utf16be"]);
+    assert_eq!(output.status.code(), Some(3));
+    assert!(stderr(&output).contains("did not contain valid UTF-16BE"));
+}
+
+#[test]
+fn skip_lines_drops_the_header() {
+    let file = fixture("header.txt", "name,xyz\nab\nba\nqq\n");
+    let out = stdout(&par_calc(&["count", file.to_str().unwrap(), "-q", "--skip-lines", "1", "--skip-last", "1", "-s", "all"]));
+    assert!(out.ends_with("Most frequent characters:\n - 'a': 2 occurrences\n - 'b': 2 occurrences\n"), "{out}");
+    let out = stdout(&par_calc(&["count", file.to_str().unwrap(), "-q", "--line-range", "2:4", "--skip-lines", "1", "-s", "all"]));
+    assert!(out.ends_with("Most frequent characters:\n - 'q': 2 occurrences\n - 'a': 1 occurrences\n - 'b': 1 occurrences\n"), "{out}");
+}
+
+#[test]
+fn skipping_every_line_is_an_error() {
+    let file = fixture("header_only.txt", "name,xyz\n");
+    let output = par_calc(&["count", file.to_str().unwrap(), "--skip-lines", "1"]);
+    assert_eq!(output.status.code(), Some(64));
+    assert!(stderr(&output).contains("leaves nothing of the 1 lines"), "{}", stderr(&output));
+}
+
+#[test]
+fn cooccur_lists_pairs_by_lines() {
+    let file = fixture("cooccur.txt", "ab\nba\nbc\n");
+    let out = stdout(&par_calc(&["count", file.to_str().unwrap(), "-q", "--no-summary", "--cooccur", "-s", "2"]));
+    assert!(out.ends_with("Characters most often on the same line:\n - 'a' and 'b': 2 lines\n - 'b' and 'c': 1 lines\n"), "{out}");
+}
+
+#[test]
+fn stats_all_lists_every_character() {
+    let file = fixture("stats_all.txt", "abcdefghijklmnopqrstuvwxyz\n");
+    let out = stdout(&par_calc(&["count", file.to_str().unwrap(), "-q", "--no-summary", "--stats", "all", "--sort-by", "char-desc"]));
+    let listed: Vec<&str> = out.lines().skip(2).collect();
+    assert_eq!(listed.len(), 26);
+    assert_eq!(listed[0], " - 'z': 1 occurrences");
+}
+
+#[test]
+fn missing_file_exits_with_2() {
+    let output = par_calc(&["count", "no_such_file.txt"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(stderr(&output), "error: cannot open 'no_such_file.txt': No such file or directory\n");
+}
+
+#[test]
+fn invalid_utf8_exits_with_3() {
+    let file = std::env::temp_dir().join(format!("par_calc_{}_latin1.txt", std::process::id()));
+    std::fs::write(&file, b"caf\xe9\n").unwrap();
+    let output = par_calc(&["count", file.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(3));
+    assert_eq!(stderr(&output), format!("error: cannot read '{}': stream did not contain valid UTF-8\n", file.display()));
+}
+
+#[test]
+fn min_line_len_1_drops_blank_lines() {
+    let file = fixture("blank_lines.txt", "ab\n\n\nc\n\n");
+    let summary = |extra: &[&str]| {
+        let out = stdout(&par_calc(&[&["count", file.to_str().unwrap(), "-q"], extra].concat()));
+        out.lines().nth(1).unwrap().to_string()
+    };
+    assert_eq!(summary(&[]), "Total lines: 5, Total characters: 3");
+    assert_eq!(summary(&["--min-line-len", "1"]), "Total lines: 2, Total characters: 3");
+    assert_eq!(summary(&["--min-line-len", "2"]), "Total lines: 1, Total characters: 2");
+}
+
+#[test]
+fn line_range_past_the_end_exits_with_64() {
+    let file = fixture("short.txt", "abc\n");
+    let output = par_calc(&["count", file.to_str().unwrap(), "--line-range", "1:5"]);
+    assert_eq!(output.status.code(), Some(64));
+    assert_eq!(stderr(&output), format!("error: line range end 5 is past the end of '{}'\n", file.display()));
+}
+
+#[test]
+fn unwritable_bench_json_names_its_own_path() {
+    let file = fixture("unwritable_bench.txt", "abc\n");
+    let output = par_calc(&["bench", file.to_str().unwrap(), "-m", "1", "-r", "1", "--bench-json", "/nonexistent/bench.json"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stderr(&output).ends_with("error: cannot write '/nonexistent/bench.json': No such file or directory\n"), "{}", stderr(&output));
+}
+
+#[test]
+fn unwritable_output_exits_with_1() {
+    let file = fixture("unwritable.txt", "abc\n");
+    let output = par_calc(&["count", file.to_str().unwrap(), "--output", "/nonexistent/report.txt"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stderr(&output).ends_with("error: cannot write '/nonexistent/report.txt': No such file or directory\n"), "{}", stderr(&output));
+}
+
+#[test]
+fn title_labels_every_csv_record() {
+    let file = fixture("title.txt", "aab\n");
+    let out = stdout(&par_calc(&["count", file.to_str().unwrap(), "-q", "--format", "csv", "--title", "run 1"]));
+    assert_eq!(out, "title,char,count\n\"run 1\",\"a\",2\n\"run 1\",\"b\",1\n");
+}
+
+#[test]
+fn serve_counts_stdin_until_it_ends() {
+    use std::io::Write;
+    use std::process::Stdio;
+    let mut child = Command::new(env!("CARGO_BIN_EXE_par_calc"))
+        .args(["serve", "--top", "1"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(b"ab\nbb\n").unwrap();
+    let out = stdout(&child.wait_with_output().unwrap());
+    assert_eq!(out, "Most frequent characters:\n - 'b': 3 occurrences\n");
+}
+
+#[test]
+fn watch_reports_again_when_the_file_is_replaced() {
+    use std::io::{BufRead, BufReader};
+    use std::process::Stdio;
+    use std::sync::mpsc;
+    use std::time::Duration;
+    let file = fixture("watch.txt", "aa\n");
+    let mut child = Command::new(env!("CARGO_BIN_EXE_par_calc"))
+        .args(["count", file.to_str().unwrap(), "--stats", "1", "--watch"])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let (send, lines) = mpsc::channel();
+    for out in [Box::new(child.stdout.take().unwrap()) as Box<dyn std::io::Read + Send>, Box::new(child.stderr.take().unwrap())] {
+        let send = send.clone();
+        std::thread::spawn(move || BufReader::new(out).lines().map_while(Result::ok).try_for_each(|line| send.send(line)));
+    }
+    let mut seen = Vec::new();
+    let mut wait_for = |wanted: &str| loop {
+        let line = lines.recv_timeout(Duration::from_secs(10)).unwrap_or_else(|_| panic!("no {wanted:?} in {seen:#?}"));
+        seen.push(line.clone());
+        if line.contains(wanted) {
+            return line;
+        }
+    };
+    wait_for(" - 'a': 2 occurrences");
+    wait_for("Watching ");
+    // Like an editor that writes a new file and renames it over the old one
+    let new = fixture("watch.txt.new", "bbb\n");
+    std::fs::rename(&new, &file).unwrap();
+    let stamp = wait_for("--- Changed at ");
+    assert!(stamp.ends_with(&format!("{}: 4 bytes ---", file.display())), "{stamp}");
+    wait_for(" - 'b': 3 occurrences");
+    child.kill().unwrap();
+    child.wait().unwrap();
+}
+
+#[test]
+fn top_lines_name_the_file_and_line() {
+    let (a, b) = (fixture("top_lines_a.txt", "hello\nxx\neee e\n"), fixture("top_lines_b.txt", "eeeeee\n"));
+    let out = stdout(&par_calc(&["count", a.to_str().unwrap(), b.to_str().unwrap(), "-q", "--no-summary", "--top-lines", "2"]));
+    let (a, b) = (a.display(), b.display());
+    assert!(out.ends_with(&format!("Lines with the most 'e':\n - {b}:1: 6 occurrences\n - {a}:3: 4 occurrences\n")), "{out}");
+}
+
+#[test]
+fn windows_get_a_distribution_each() {
+    let (a, b) = (fixture("window_a.txt", "aa\nab\nbb\n"), fixture("window_b.txt", "cc\n"));
+    let (a, b) = (a.to_str().unwrap(), b.to_str().unwrap());
+    let out = stdout(&par_calc(&["stats", a, b, "--window", "2", "--top", "1", "-q"]));
+    assert_eq!(out, format!(
+        "=== {a}, {b} ===\n=== Lines 1-2 ===\nTotal lines: 2, Total characters: 4\nMost frequent characters:\n - 'a': 3 occurrences\n\
+         === Lines 3-4 ===\nTotal lines: 2, Total characters: 4\nMost frequent characters:\n - 'b': 2 occurrences\n"
+    ));
+    let out = stdout(&par_calc(&["stats", a, b, "--window", "3", "--top", "2", "-f", "jsonl", "-q"]));
+    let windows: Vec<serde_json::Value> = out.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+    assert_eq!(windows, [
+        serde_json::json!({"window": 1, "first_line": 1, "last_line": 3, "total_chars": 6, "counts": [{"char": "a", "count": 3}, {"char": "b", "count": 3}]}),
+        serde_json::json!({"window": 2, "first_line": 4, "last_line": 4, "total_chars": 2, "counts": [{"char": "c", "count": 2}]}),
+    ]);
+    let output = par_calc(&["stats", a, "--window", "2", "-f", "csv"]);
+    assert_eq!((output.status.code(), stderr(&output).as_str()), (Some(64), "error: --window needs --format human or jsonl\n"));
+}
+
+#[test]
+fn no_whitespace_leaves_out_unicode_spaces_too() {
+    let file = fixture("no_whitespace.txt", "a a\tb\n\u{3000}b\u{a0}c\n");
+    for engine in ["serial", "parallel"] {
+        let out = stdout(&par_calc(&["count", file.to_str().unwrap(), "--no-whitespace", "--engine", engine, "-f", "csv", "-q"]));
+        assert_eq!(out, "char,count\n\"a\",2\n\"b\",2\n\"c\",1\n", "{engine}");
+    }
+    let output = par_calc(&["count", file.to_str().unwrap(), "--no-whitespace", "--verify"]);
+    assert_eq!(output.status.code(), Some(64));
+}
+
+#[test]
+fn completions_cover_every_shell() {
+    for shell in ["bash", "zsh", "fish", "powershell"] {
+        let out = stdout(&par_calc(&["completions", shell]));
+        assert!(out.contains("threads-fixed") && out.contains("reruns") && out.contains("sort-by"), "{shell}: {out}");
+    }
+}
+
+#[test]
+fn append_results_adds_a_section_per_run() {
+    let file = fixture("append.txt", "ab\n");
+    let results = std::env::temp_dir().join(format!("par_calc_{}_append.csv", std::process::id()));
+    let _ = std::fs::remove_file(&results);
+    for title in ["seed 1", "seed 2"] {
+        let args = ["count", file.to_str().unwrap(), "-q", "-f", "csv", "--title", title, "--append-results", results.to_str().unwrap()];
+        stdout(&par_calc(&args));
+    }
+    let lines: Vec<String> = std::fs::read_to_string(&results).unwrap().lines().map(String::from).collect();
+    assert_eq!(lines.len(), 9);
+    assert_eq!(lines[0], "# par_calc results, one section per run");
+    for (section, title) in [(&lines[1..5], "seed 1"), (&lines[5..9], "seed 2")] {
+        assert!(section[0].starts_with("# run at ") && section[0].ends_with(&format!(": {title}")));
+        assert_eq!(section[1..], [
+            "title,char,count".to_string(),
+            format!("\"{title}\",\"a\",1"),
+            format!("\"{title}\",\"b\",1"),
+        ]);
+    }
+}
+
+// Runs --print-config in a fresh directory holding `config` as rust-lab9.toml
+fn print_config(name: &str, config: Option<&str>, args: &[&str]) -> Output {
+    let dir = std::env::temp_dir().join(format!("par_calc_{}_{name}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    if let Some(config) = config {
+        std::fs::write(dir.join("rust-lab9.toml"), config).unwrap();
+    }
+    let file = fixture(&format!("{name}.txt"), "abc\n");
+    Command::new(env!("CARGO_BIN_EXE_par_calc"))
+        .current_dir(&dir)
+        .args(["bench", file.to_str().unwrap(), "--print-config"])
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn config_defaults_without_a_file() {
+    let out = stdout(&print_config("config_none", None, &[]));
+    assert_eq!(out, "max = 8  # default\nreruns = 100  # default\nformat = \"human\"  # default\nengine = \"parallel\"  # default\nfold_case = false  # default\n");
+}
+
+#[test]
+fn config_file_overrides_defaults() {
+    let out = stdout(&print_config("config_file", Some("max = 2\nreruns = 5\nformat = \"csv\"\n"), &[]));
+    assert_eq!(out, "max = 2  # rust-lab9.toml\nreruns = 5  # rust-lab9.toml\nformat = \"csv\"  # rust-lab9.toml\nengine = \"parallel\"  # default\nfold_case = false  # default\n");
+}
+
+#[test]
+fn command_line_overrides_config_file() {
+    let out = stdout(&print_config("config_cli", Some("max = 2\nreruns = 5\n"), &["--reruns", "7", "-f", "json"]));
+    assert_eq!(out, "max = 2  # rust-lab9.toml\nreruns = 7  # command line\nformat = \"json\"  # command line\nengine = \"parallel\"  # default\nfold_case = false  # default\n");
+}
+
+#[test]
+fn config_file_sets_the_engine_and_case_folding() {
+    let config = "engine = \"serial\"\nfold_case = true\n";
+    let out = stdout(&print_config("config_engine", Some(config), &[]));
+    assert!(out.ends_with("engine = \"serial\"  # rust-lab9.toml\nfold_case = true  # rust-lab9.toml\n"), "{out}");
+    let out = stdout(&print_config("config_engine_cli", Some(config), &["--engine", "parallel", "--no-fold-case"]));
+    assert!(out.ends_with("engine = \"parallel\"  # command line\nfold_case = false  # command line\n"), "{out}");
+    let out = stdout(&print_config("config_engine_last", Some("fold_case = false\n"), &["--no-fold-case", "--fold-case"]));
+    assert!(out.ends_with("fold_case = true  # command line\n"), "{out}");
+    // And the count goes by it
+    let file = fixture("config_fold_case.txt", "aA\n");
+    let explicit = fixture("config_fold_case.toml", config);
+    let folded = stdout(&par_calc(&["count", file.to_str().unwrap(), "--config", explicit.to_str().unwrap(), "-q", "-f", "csv"]));
+    assert_eq!(folded, "char,count\n\"a\",2\n");
+    let apart = stdout(&par_calc(&["count", file.to_str().unwrap(), "--config", explicit.to_str().unwrap(), "-q", "-f", "csv", "--no-fold-case"]));
+    assert_eq!(apart, "char,count\n\"A\",1\n\"a\",1\n");
+}
+
+#[test]
+fn unknown_config_engine_is_rejected() {
+    let output = print_config("config_bad_engine", Some("engine = \"gpu\"\n"), &[]);
+    assert_eq!(output.status.code(), Some(3));
+    let err = stderr(&output);
+    assert!(err.contains("line 1") && err.contains("unknown engine 'gpu', expected one of serial, parallel"), "{err}");
+}
+
+#[test]
+fn config_path_can_be_given() {
+    let config = fixture("explicit.toml", "reruns = 3\n");
+    let out = stdout(&print_config("config_explicit", Some("reruns = 5\n"), &["--config", config.to_str().unwrap()]));
+    assert!(out.contains(&format!("reruns = 3  # {}\n", config.display())), "{out}");
+}
+
+#[test]
+fn unknown_config_key_is_reported_with_its_line() {
+    let output = print_config("config_unknown", Some("max = 2\nwarmup = 3\n"), &[]);
+    assert_eq!(output.status.code(), Some(3));
+    let err = stderr(&output);
+    assert!(err.contains("rust-lab9.toml") && err.contains("line 2") && err.contains("unknown field `warmup`"), "{err}");
+}
+
+#[test]
+fn mistyped_config_value_is_reported_with_its_line() {
+    let output = print_config("config_type", Some("reruns = \"many\"\n"), &[]);
+    assert_eq!(output.status.code(), Some(3));
+    let err = stderr(&output);
+    assert!(err.contains("line 1") && err.contains("invalid type: string \"many\", expected u32"), "{err}");
+}
+
+#[test]
+fn stats_load_reports_on_saved_results() {
+    let file = fixture("save.txt", "aaab\nbc\n");
+    let saved = std::env::temp_dir().join(format!("par_calc_{}_saved.json", std::process::id()));
+    stdout(&par_calc(&["count", file.to_str().unwrap(), "-q", "--save-results", saved.to_str().unwrap()]));
+    let out = stdout(&par_calc(&["stats", "--load", saved.to_str().unwrap(), "--top", "2", "--bottom", "1"]));
+    assert_eq!(
+        out,
+        format!(
+            "=== {} ===\nTotal lines: 2, Total characters: 6\nMost frequent characters:\n - 'a': 3 occurrences\n - 'b': 2 occurrences\nLeast frequent characters:\n - 'c': 1 occurrences\n",
+            saved.display()
+        )
+    );
+}
+
+#[test]
+fn top_percent_lists_the_characters_making_up_that_share() {
+    let file = fixture("top_percent.txt", "aaaaabbbc\n");
+    // Without the === title === line
+    let list = |args: &[&str]| {
+        let out = stdout(&par_calc(&[&["stats", file.to_str().unwrap(), "-q", "--no-summary"][..], args].concat()));
+        out.split_once('\n').unwrap().1.to_string()
+    };
+    assert_eq!(list(&["--top-percent", "50"]), "Most frequent characters:\n - 'a': 5 occurrences\n");
+    assert_eq!(list(&["--top-percent", "60"]), "Most frequent characters:\n - 'a': 5 occurrences\n - 'b': 3 occurrences\n");
+    assert_eq!(list(&["--top-percent", "88", "--sort-by", "count-asc"]), "Characters:\n - 'b': 3 occurrences\n - 'a': 5 occurrences\n");
+    assert_eq!(list(&["--top-percent", "100"]).lines().count(), 4);
+    for bad in ["0", "100.5", "-5"] {
+        assert_eq!(par_calc(&["stats", file.to_str().unwrap(), "--top-percent", bad]).status.code(), Some(64), "{bad}");
+    }
+}
+
+fn saved_counts(name: &str, content: &str) -> String {
+    let file = fixture(&format!("{name}.txt"), content);
+    let saved = std::env::temp_dir().join(format!("par_calc_{}_{name}.json", std::process::id()));
+    stdout(&par_calc(&["count", file.to_str().unwrap(), "-q", "--save-results", saved.to_str().unwrap()]));
+    saved.to_str().unwrap().to_string()
+}
+
+#[test]
+fn compare_lists_the_largest_changes_first() {
+    let (a, b) = (saved_counts("compare_a", "aab\n"), saved_counts("compare_b", "aaaac\n"));
+    let out = stdout(&par_calc(&["compare", &a, &b, "--stats", "2"]));
+    let mut lines = out.lines().skip(1);
+    assert!(lines.next().unwrap().ends_with("+2 (+100.00%)"), "{out}");
+    assert!(lines.next().unwrap().starts_with("'b'"), "{out}");
+    assert!(out.contains("Cosine similarity: 0.8"), "{out}");
+}
+
+#[test]
+fn compare_fails_below_the_similarity_threshold() {
+    let (a, b) = (saved_counts("similar_a", "ab\n"), saved_counts("similar_b", "cd\n"));
+    let output = par_calc(&["compare", &a, &b, "--threshold", "0.5"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stderr(&output).contains("error: cosine similarity 0.000000 is below the threshold 0.5"), "{}", stderr(&output));
+    assert_eq!(par_calc(&["compare", &a, &a, "--threshold", "0.5"]).status.code(), Some(0));
+}
+
+#[test]
+fn color_never_and_machine_formats_have_no_escapes() {
+    let file = fixture("color.txt", "aab\n");
+    let path = file.to_str().unwrap();
+    let colored = par_calc(&["bench", path, "--color", "always", "-m", "2", "-r", "1", "--stats", "2"]);
+    assert!(stdout(&colored).contains('\x1b'));
+    for args in [&["--color", "never"][..], &["--color", "always", "--format", "csv"], &["--color", "always", "--format", "json"]] {
+        let output = par_calc(&[&["bench", path, "-m", "2", "-r", "1", "--stats", "2"][..], args].concat());
+        assert!(!stdout(&output).contains('\x1b'), "{args:?}");
+        if args[1] == "never" {
+            assert!(!stderr(&output).contains('\x1b'));
+        }
+    }
+}
+
+#[test]
+fn fold_case_follows_the_locale() {
+    let file = fixture("turkish.txt", "Iı\n");
+    let path = file.to_str().unwrap();
+    let default = stdout(&par_calc(&["count", path, "-q", "-f", "csv", "--fold-case"]));
+    assert_eq!(default, "char,count\n\"i\",1\n\"ı\",1\n");
+    let turkish = stdout(&par_calc(&["count", path, "-q", "-f", "csv", "--fold-case", "--locale", "tr"]));
+    assert_eq!(turkish, "char,count\n\"ı\",2\n");
+}
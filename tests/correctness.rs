@@ -0,0 +1,378 @@
+use std::collections::HashMap;
+use std::io::BufRead;
+use std::time::Duration;
+use par_calc::{benchmark, compress_counter, concentration_report, count_chars, count_chars_from_reader, count_chars_from_strs, count_chars_iter, count_chars_parallel_from_reader, count_chars_parallel, count_chars_text_parallel, count_chunks, count_chunks_queued, count_chunks_with, count_graphemes_bounded, count_into, count_non_whitespace, count_text, count_units, load_file, merge_compressed, merge_counts, split_at_char_boundaries, split_input, top_contributing_lines, top_contributing_lines_parallel, top_counts, total_chars, Balance, CharCounter, CountError, InputMeta, LatencyStats, Lines, LoadError, RunReport, Split, Unit};
+use proptest::prelude::*;
+
+proptest! {
+    #[test]
+    fn merge_compressed_matches_merge_counts(a in prop::collection::vec(any::<String>(), 0..16), b in prop::collection::vec(any::<String>(), 0..16)) {
+        let (a, b) = (count_chars(&a), count_chars(&b));
+        let merged = merge_compressed(compress_counter(&a), compress_counter(&b));
+        prop_assert!(merged.0.windows(2).all(|pair| pair[0].0 < pair[1].0));
+        let mut expected = a;
+        merge_counts(&mut expected, &b);
+        prop_assert_eq!(merged.into_counts(), expected);
+    }
+
+    #[test]
+    fn count_text_matches_count_chars(text in any::<String>()) {
+        let counts = count_text(&text);
+        prop_assert!(counts.keys().zip(counts.keys().skip(1)).all(|(a, b)| a < b));
+        prop_assert_eq!(counts.into_iter().collect::<CharCounter>(), count_chars(&[&text]));
+    }
+
+    #[test]
+    fn byte_segments_split_no_character(text in any::<String>(), n in 1usize..16) {
+        let segments = split_at_char_boundaries(text.as_bytes(), n);
+        prop_assert!(segments.len() <= n);
+        prop_assert!(segments.iter().all(|segment| std::str::from_utf8(segment).is_ok()));
+        prop_assert_eq!(segments.concat(), text.as_bytes());
+        prop_assert_eq!(count_chars_text_parallel(&text, n).unwrap(), count_chars(&[&text]));
+    }
+
+    #[test]
+    fn run_report_round_trips(input in prop::collection::vec(any::<String>(), 0..16)) {
+        let counts = count_chars(&input);
+        let report = RunReport::new(InputMeta::default(), &counts);
+        let read: RunReport = serde_json::from_str(&serde_json::to_string(&report).unwrap()).unwrap();
+        prop_assert_eq!(read.char_counts(), counts);
+        prop_assert_eq!(read.stats.total_chars, report.stats.total_chars);
+    }
+
+    #[test]
+    fn parallel_matches_serial(input in prop::collection::vec(any::<String>(), 0..64)) {
+        let expected = count_chars(&input);
+        for n in 1..=8 {
+            prop_assert_eq!(&count_chars_parallel(&input, n).unwrap(), &expected);
+        }
+    }
+
+    #[test]
+    fn queued_chunks_match_serial(input in prop::collection::vec(any::<String>(), 0..64), n in 1usize..8, chunk_lines in 1usize..10) {
+        let (counts, work) = count_chunks_queued(&input, n, chunk_lines).unwrap();
+        prop_assert_eq!(&counts, &count_chars(&input));
+        prop_assert!(work.len() <= n);
+        prop_assert_eq!(work.iter().sum::<u64>(), counts.total());
+    }
+
+    #[test]
+    fn chars_split_counts_like_lines_split(input in prop::collection::vec("\\PC{0,12}|a{0,200}", 0..64), n in 1usize..8, exact in any::<bool>()) {
+        let by_lines = split_input(&input, n, Split { exact, ..Split::default() });
+        let by_chars = split_input(&input, n, Split { balance: Balance::Chars, exact, ..Split::default() });
+        prop_assert_eq!(by_chars.concat(), input.clone());
+        prop_assert_eq!(count_chunks(&by_chars).unwrap().0, count_chunks(&by_lines).unwrap().0);
+    }
+
+    #[test]
+    fn top_lines_parallel_match_serial(input in prop::collection::vec("[ab]{0,6}", 0..64), n in 0usize..10, threads in 1usize..8) {
+        let top = top_contributing_lines(&input, 'a', n);
+        prop_assert!(top.len() <= n && top.windows(2).all(|pair| pair[0].1 > pair[1].1 || (pair[0].1 == pair[1].1 && pair[0].0 < pair[1].0)));
+        prop_assert!(top.iter().all(|&(i, count)| input[i].matches('a').count() == count));
+        prop_assert_eq!(top_contributing_lines_parallel(&input, 'a', n, threads).unwrap(), top);
+    }
+
+    #[test]
+    fn non_whitespace_counts_skip_exactly_the_whitespace(input in prop::collection::vec("[ a\t\u{b}\u{c}\r\u{85}\u{a0}\u{2028}\u{3000}é]{0,20}|\\PC{0,10}", 0..32)) {
+        let mut expected = count_chars(&input);
+        expected.retain(|c, _| !c.is_whitespace());
+        prop_assert_eq!(&count_non_whitespace(&input), &expected);
+        let chunks: Vec<&[String]> = input.chunks(5).collect();
+        prop_assert_eq!(&count_chunks_with(&chunks, count_non_whitespace).unwrap().0, &expected);
+    }
+
+    #[test]
+    fn top_k_matches_the_full_sort(counts in prop::collection::hash_map("[a-z]{1,3}", 0..8u64, 0..200), k in 0usize..40) {
+        // Counts below 8 make most keys tie, so the key order decides which of them are kept
+        let mut sorted: Vec<(String, u64)> = counts.clone().into_iter().collect();
+        sorted.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        sorted.truncate(k);
+        prop_assert_eq!(top_counts(counts.clone(), k), sorted);
+        let chars: CharCounter = counts.into_iter().map(|(key, n)| (key.chars().next().unwrap(), n)).collect();
+        let mut sorted = chars.top_n(usize::MAX);
+        sorted.truncate(k);
+        prop_assert_eq!(chars.top_n(k), sorted);
+        let mut bottom = chars.bottom_n(usize::MAX);
+        bottom.truncate(k);
+        prop_assert_eq!(chars.bottom_n(k), bottom);
+    }
+
+    #[test]
+    fn iterator_matches_slice(input in prop::collection::vec(any::<String>(), 0..64)) {
+        prop_assert_eq!(count_chars_iter(input.iter().flat_map(|line| line.chars())), count_chars(&input));
+    }
+
+    #[test]
+    fn percentiles_are_ordered(nanos in prop::collection::vec(0..u32::MAX as u64, 1..200)) {
+        let mut times: Vec<Duration> = nanos.into_iter().map(Duration::from_nanos).collect();
+        let stats = LatencyStats::from_times(&mut times);
+        prop_assert!(stats.min <= stats.p50 && stats.p50 <= stats.p90 && stats.p90 <= stats.p95 && stats.p95 <= stats.p99 && stats.p99 <= stats.max);
+        prop_assert!(stats.min <= stats.mean && stats.mean <= stats.max);
+    }
+
+    #[test]
+    fn counts_sum_to_total_chars(input in prop::collection::vec(any::<String>(), 0..64)) {
+        let total: u64 = input.iter().map(|line| line.chars().count() as u64).sum();
+        prop_assert_eq!(count_chars(&input).values().sum::<u64>(), total);
+    }
+
+    #[test]
+    fn readers_count_like_loaded_lines(text in "(\\PC|[\r\n]){0,300}", batch in 1..10usize) {
+        let lines: Vec<String> = text.as_bytes().lines().map(Result::unwrap).collect();
+        let expected = count_chars(&lines);
+        // A buffer smaller than most lines, so that they have to be read in pieces
+        let reader = || std::io::BufReader::with_capacity(4, text.as_bytes());
+        prop_assert_eq!(&count_chars_from_reader(reader()).unwrap(), &expected);
+        prop_assert_eq!(&count_chars_parallel_from_reader(reader(), 3, batch).unwrap(), &expected);
+        prop_assert_eq!(&count_chars_from_strs(&lines), &expected);
+    }
+
+    #[test]
+    fn lines_split_like_bufread(text in "(\\PC|[\r\n]){0,200}") {
+        let expected: Vec<String> = text.as_bytes().lines().map(Result::unwrap).collect();
+        let (split, joined) = (Lines::from(text.clone()), expected.iter().collect::<Lines>());
+        prop_assert_eq!(split.strs(), joined.strs());
+        prop_assert_eq!(joined.strs(), expected);
+    }
+
+    #[test]
+    fn bounded_graphemes_that_are_kept_are_exact(input in prop::collection::vec("[a-e\r\n]{0,40}|\\PC{0,10}", 0..32), max_keys in 2..8usize) {
+        let expected = count_units(&input, &[Unit::Grapheme]).graphemes.unwrap();
+        let bounded = count_graphemes_bounded(&input, max_keys).unwrap();
+        prop_assert!(bounded.graphemes.len() <= max_keys);
+        for (g, n) in &bounded.graphemes {
+            prop_assert_eq!(expected[g], *n);
+        }
+        prop_assert_eq!(bounded.graphemes.values().sum::<u64>() + bounded.dropped, expected.values().sum::<u64>());
+    }
+}
+
+#[test]
+fn merging_saturates_instead_of_wrapping() {
+    let mut counts = CharCounter::from_iter([('a', u64::MAX - 1), ('b', u64::MAX)]);
+    merge_counts(&mut counts, &count_chars(&["aaa"]));
+    assert_eq!(counts[&'a'], u64::MAX);
+    assert_eq!(total_chars(&counts), u64::MAX);
+}
+
+#[test]
+fn uniform_counts_need_a_proportional_share_of_characters() {
+    let counts: CharCounter = ('a'..='t').map(|c| (c, 7)).collect();
+    assert_eq!(concentration_report(&counts).levels, [(50, 10), (80, 16), (95, 19), (99, 20)]);
+}
+
+#[test]
+fn counts_chars_from_any_iterator() {
+    assert!(count_chars_iter(std::iter::empty()).is_empty());
+    assert_eq!(count_chars_iter(std::iter::repeat_n('x', 1000))[&'x'], 1000);
+    let counts = count_chars_iter(('a'..='e').cycle().take(12));
+    assert_eq!((counts[&'a'], counts[&'b'], counts[&'e']), (3, 3, 2));
+}
+
+// A line that panics when counted
+struct Poisoned(&'static str);
+
+impl AsRef<str> for Poisoned {
+    fn as_ref(&self) -> &str {
+        if self.0 == "boom" {
+            panic!("poisoned line");
+        }
+        self.0
+    }
+}
+
+#[test]
+fn a_panicking_queued_chunk_is_reported_by_its_index() {
+    let lines: Vec<Poisoned> = ["a", "b", "c", "d", "e", "f", "boom", "g", "h"].into_iter().map(Poisoned).collect();
+    for threads in [1, 2, 4] {
+        assert_eq!(count_chunks_queued(&lines, threads, 2), Err(CountError::WorkerPanic { chunk_index: 3, messages: vec!["poisoned line".to_string()] }), "{threads} threads");
+    }
+}
+
+#[test]
+fn counts_go_past_u32_max() {
+    let mut counts = CharCounter::from_iter([('a', u32::MAX as u64)]);
+    count_into(&mut counts, "aa");
+    merge_counts(&mut counts, &count_chars(&["a"]));
+    assert_eq!(counts[&'a'], u32::MAX as u64 + 3);
+}
+
+#[test]
+fn a_panicking_worker_fails_the_whole_count() {
+    let lines = ["a", "b", "c", "d"];
+    let chunks: Vec<&[&str]> = lines.chunks(1).collect();
+    let engine = |chunk: &[&str]| if chunk[0] == "c" { panic!("engine failure") } else { count_chars(chunk) };
+    assert_eq!(count_chunks_with(&chunks, engine), Err(CountError::WorkerPanic { chunk_index: 2, messages: vec!["engine failure".to_string()] }));
+}
+
+#[test]
+fn every_panicking_worker_gets_its_message_reported() {
+    let lines = ["a", "b", "c", "d", "e"];
+    let chunks: Vec<&[&str]> = lines.chunks(1).collect();
+    let engine = |chunk: &[&str]| match chunk[0] {
+        "b" => panic!("no {}", chunk[0]),
+        "d" => panic!("out of memory"),
+        _ => count_chars(chunk),
+    };
+    let err = count_chunks_with(&chunks, engine).unwrap_err();
+    assert_eq!(err, CountError::WorkerPanic { chunk_index: 1, messages: vec!["no b".to_string(), "out of memory".to_string()] });
+    assert_eq!(err.to_string(), "the thread counting chunk 1 and 1 others panicked: no b; out of memory");
+}
+
+// Hands out `data` and then fails, like a connection dropping part way through
+struct FailingReader<'a> {
+    data: &'a [u8],
+}
+
+impl std::io::Read for FailingReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.data.is_empty() {
+            return Err(std::io::Error::new(std::io::ErrorKind::ConnectionReset, "gone"));
+        }
+        let n = buf.len().min(self.data.len());
+        buf[..n].copy_from_slice(&self.data[..n]);
+        self.data = &self.data[n..];
+        Ok(n)
+    }
+}
+
+#[test]
+fn reader_errors_part_way_through_are_returned() {
+    let reader = || std::io::BufReader::new(FailingReader { data: b"complete line\npartial" });
+    assert_eq!(count_chars_from_reader(reader()).unwrap_err().kind(), std::io::ErrorKind::ConnectionReset);
+    assert_eq!(count_chars_parallel_from_reader(reader(), 2, 1).unwrap_err().kind(), std::io::ErrorKind::ConnectionReset);
+}
+
+#[test]
+fn run_report_round_trips_non_bmp_chars_and_bench_results() {
+    let counts = count_chars(&["𝄞😀a", "😀\u{10FFFF}"]);
+    let bench = vec![benchmark(&["𝄞😀a"], 2, 1, Split::default()).unwrap()];
+    let input = InputMeta { fold_case: true, max_len: Some(80), encoding: Some("UTF-16LE".to_string()), threads: 2, ..InputMeta::default() };
+    let report = RunReport { bench: Some(bench.clone()), ..RunReport::new(input.clone(), &counts) };
+    let json = serde_json::to_string_pretty(&report).unwrap();
+    assert!(json.contains("\"😀\": 2") && json.contains("\"𝄞\": 1"), "{json}");
+    let read: RunReport = serde_json::from_str(&json).unwrap();
+    assert_eq!((read.char_counts(), read.input, read.stats), (counts, input, report.stats));
+    let read_bench = read.bench.unwrap();
+    assert_eq!(read_bench.len(), bench.len());
+    for (read, result) in read_bench.iter().zip(&bench) {
+        assert_eq!((read.threads, read.latency, read.total, &read.work), (result.threads, result.latency, result.total, &result.work));
+    }
+}
+
+#[cfg(feature = "rayon")]
+#[test]
+fn par_bridge_matches_serial_count_of_the_file() {
+    let path = std::env::temp_dir().join(format!("par_calc_{}_par_bridge.txt", std::process::id()));
+    let text = "first line\nsecond 𝄞\r\n\nlast".repeat(500);
+    std::fs::write(&path, &text).unwrap();
+    let counts = par_calc::count_chars_par_bridge(path.to_str().unwrap()).unwrap();
+    assert_eq!(counts, count_chars(&text.lines().collect::<Vec<_>>()));
+    assert!(par_calc::count_chars_par_bridge("/nonexistent/par_calc").is_err());
+}
+
+#[test]
+fn line_ranges_take_the_lines_from_start_to_end() {
+    let path = std::env::temp_dir().join(format!("par_calc_{}_line_range.txt", std::process::id()));
+    std::fs::write(&path, (1..=10).map(|i| format!("line {i}\n")).collect::<String>()).unwrap();
+    let path = path.to_str().unwrap();
+    assert_eq!(load_file(path, None).unwrap().len(), 10);
+    assert_eq!(load_file(path, Some((3, 5))).unwrap(), ["line 3", "line 4", "line 5"]);
+    assert_eq!(load_file(path, Some((1, 1))).unwrap(), ["line 1"]);
+    assert_eq!(load_file(path, Some((10, 10))).unwrap(), ["line 10"]);
+    assert_eq!(load_file(path, Some((1, 10))).unwrap(), load_file(path, None).unwrap());
+    match load_file(path, Some((9, 11))) {
+        Err(LoadError::PastEnd(name, 11)) => assert_eq!(name, path),
+        other => panic!("{other:?}"),
+    }
+    assert!(matches!(load_file(path, Some((11, 12))), Err(LoadError::PastEnd(_, 12))));
+    for (start, end) in [(0, 3), (5, 3)] {
+        match load_file(path, Some((start, end))) {
+            Err(LoadError::InvalidRange(name, s, e)) => assert_eq!((name.as_str(), s, e), (path, start, end)),
+            other => panic!("{other:?}"),
+        }
+        assert!(matches!(par_calc::load_lines(path, Some((start, end))), Err(LoadError::InvalidRange(..))));
+    }
+}
+
+#[test]
+fn multibyte_chars_straddling_the_byte_cuts_are_counted_once() {
+    // 12 bytes split 3 ways cut at bytes 4 and 8, inside the 4-byte '🎉' and the 3-byte '日'
+    let text = "ab🎉日本";
+    assert_eq!(split_at_char_boundaries(text.as_bytes(), 3), ["ab🎉", "日", "本"].map(str::as_bytes));
+    for c in ['€', '日', '🎉'] {
+        let text: String = std::iter::repeat_n(format!("a{c}"), 101).collect();
+        for n in 1..=12 {
+            let counts = count_chars_text_parallel(&text, n).unwrap();
+            assert_eq!((counts[&'a'], counts[&c], counts.len()), (101, 101, 2), "{c} with {n} threads");
+        }
+    }
+}
+
+#[test]
+fn benchmarks_count_empty_and_unicode_input_like_one_thread() {
+    let empty: [&str; 0] = [];
+    for result in par_calc::benchmark_all(&empty, &[1, 2, 4], 2, Split::default()).unwrap() {
+        assert_eq!((result.reruns, result.work.iter().sum::<u64>()), (2, 0), "{} threads", result.threads);
+    }
+    assert!(count_chars_parallel(&empty, 4).unwrap().is_empty());
+    let unicode = ["héllo wörld", "", "𝄞😀👩‍👩‍👧", "日本語のテキスト", "e\u{301}\u{0}"];
+    let serial = count_chars(&unicode);
+    for n in 1..=6 {
+        assert_eq!(count_chars_parallel(&unicode, n).unwrap(), serial, "{n} threads");
+        // Characters, not bytes, as the work of the threads
+        assert_eq!(benchmark(&unicode, n, 1, Split::default()).unwrap().work.iter().sum::<u64>(), serial.total(), "{n} threads");
+    }
+}
+
+#[test]
+fn zero_threads_count_like_one() {
+    let input = ["abc", "", "éé"];
+    for balance in [par_calc::Balance::Lines, par_calc::Balance::Chars] {
+        for exact in [false, true] {
+            assert_eq!(par_calc::split_input(&input, 0, Split { balance, exact, ..Split::default() }).len(), 1, "{balance:?}, exact {exact}");
+        }
+    }
+    assert_eq!(count_chars_parallel(&input, 0).unwrap(), count_chars(&input));
+    assert_eq!(benchmark(&input, 0, 0, Split::default()).unwrap().reruns, 1);
+}
+
+#[test]
+fn counters_merge_overlapping_and_disjoint_counts() {
+    let abc: CharCounter = "aabc".chars().collect();
+    let overlapping: CharCounter = "cccd".chars().collect();
+    let disjoint: CharCounter = "xyz".chars().collect();
+
+    let mut merged = abc.clone();
+    merged += &overlapping;
+    assert_eq!(merged, [('a', 2), ('b', 1), ('c', 4), ('d', 1)].into_iter().collect());
+    assert_eq!(abc.clone() + overlapping.clone(), merged);
+    assert_eq!(overlapping.clone() + abc.clone(), merged);
+
+    let all: CharCounter = [abc.clone(), overlapping, disjoint.clone()].into_iter().sum();
+    assert_eq!((all.total(), all.distinct(), all[&'x']), (11, 7, 1));
+    assert_eq!(abc.clone() + disjoint.clone(), "aabcxyz".chars().collect());
+    assert_eq!(abc.clone() + CharCounter::default(), abc);
+
+    let mut saturated: CharCounter = [('a', u64::MAX - 1)].into_iter().collect();
+    saturated.extend([('a', 5), ('b', 1)]);
+    assert_eq!((saturated[&'a'], saturated[&'b']), (u64::MAX, 1));
+}
+
+#[test]
+fn top_and_bottom_break_ties_by_code_point() {
+    let counter: CharCounter = "ccbbaa😀ezd".chars().collect();
+    assert_eq!(counter.top_n(4), [('a', 2), ('b', 2), ('c', 2), ('d', 1)]);
+    assert_eq!(counter.bottom_n(3), [('d', 1), ('e', 1), ('z', 1)]);
+    assert_eq!(counter.bottom_n(5)[4], ('a', 2));
+    assert_eq!(counter.top_n(100).len(), 7);
+    assert_eq!(counter.top_n(counter.distinct()).last(), Some(&('😀', 1)));
+}
+
+#[test]
+fn counters_convert_to_and_from_hash_maps() {
+    let map: HashMap<char, u64> = HashMap::from([('a', 3), ('🎉', 1)]);
+    let counter = CharCounter::from(map.clone());
+    assert_eq!((counter[&'a'], counter.total()), (3, 4));
+    assert_eq!(HashMap::from(counter), map);
+}
@@ -0,0 +1,59 @@
+// Allocations of count_chars_parallel and its pooled version, counted by a global allocator of
+// this test binary, which is why it is on its own and has a single test
+#![cfg(feature = "pool")]
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicU64, Ordering};
+use par_calc::{count_chars_parallel, count_chars_parallel_pooled, CounterPool};
+
+struct Counting;
+
+static ALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+static BYTES: AtomicU64 = AtomicU64::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        BYTES.fetch_add(layout.size() as u64, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        BYTES.fetch_add(new_size as u64, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+// Allocations and allocated bytes while `f` runs
+fn allocated(mut f: impl FnMut()) -> (u64, u64) {
+    let (allocations, bytes) = (ALLOCATIONS.load(Ordering::Relaxed), BYTES.load(Ordering::Relaxed));
+    f();
+    (ALLOCATIONS.load(Ordering::Relaxed) - allocations, BYTES.load(Ordering::Relaxed) - bytes)
+}
+
+#[test]
+fn pooled_reruns_allocate_less() {
+    // A few hundred distinct characters, so that every per-thread map grows a few times
+    let input: Vec<String> = (0..2000).map(|i| (0..40).map(|j| char::from_u32(0x20 + (i * 7 + j * 13) % 400).unwrap()).collect()).collect();
+    let (threads, reruns) = (8, 20);
+    let plain = allocated(|| {
+        for _ in 0..reruns {
+            drop(count_chars_parallel(&input, threads).unwrap());
+        }
+    });
+    let pool = CounterPool::new();
+    let pooled = allocated(|| {
+        for _ in 0..reruns {
+            pool.give(count_chars_parallel_pooled(&input, threads, &pool).unwrap());
+        }
+    });
+    assert_eq!(pool.len(), threads);
+    assert!(pooled.0 < plain.0 && pooled.1 < plain.1 / 2, "{plain:?} vs {pooled:?}");
+}
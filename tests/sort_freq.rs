@@ -0,0 +1,102 @@
+use par_calc::{count_chars, count_chars_ordered, list_freq, sort_freq, sort_freq_by_order, SortKey};
+
+fn sorted(key: SortKey) -> Vec<char> {
+    let mut freq = vec![('b', 3), ('A', 1), ('é', 2), ('a', 5), ('Z', 3)];
+    sort_freq(&mut freq, key);
+    freq.into_iter().map(|(c, _)| c).collect()
+}
+
+#[test]
+fn count_desc() {
+    assert_eq!(sorted(SortKey::CountDesc), ['a', 'Z', 'b', 'é', 'A']);
+}
+
+#[test]
+fn count_asc() {
+    assert_eq!(sorted(SortKey::CountAsc), ['A', 'é', 'Z', 'b', 'a']);
+}
+
+#[test]
+fn char_asc() {
+    assert_eq!(sorted(SortKey::CharAsc), ['A', 'Z', 'a', 'b', 'é']);
+}
+
+#[test]
+fn char_desc() {
+    assert_eq!(sorted(SortKey::CharDesc), ['é', 'b', 'a', 'Z', 'A']);
+}
+
+#[cfg(feature = "collation")]
+#[test]
+fn collated_asc() {
+    assert_eq!(sorted(SortKey::CollatedAsc), ['A', 'a', 'b', 'é', 'Z']);
+}
+
+#[cfg(feature = "collation")]
+#[test]
+fn collated_desc() {
+    assert_eq!(sorted(SortKey::CollatedDesc), ['Z', 'é', 'b', 'a', 'A']);
+}
+
+#[test]
+fn codepoint_asc() {
+    assert_eq!(sorted(SortKey::CodepointAsc), ['A', 'Z', 'a', 'b', 'é']);
+}
+
+#[test]
+fn codepoint_desc() {
+    assert_eq!(sorted(SortKey::CodepointDesc), ['é', 'b', 'a', 'Z', 'A']);
+}
+
+#[test]
+fn first_seen_breaks_count_ties() {
+    let (counts, order) = count_chars_ordered(&["zyx", "yz"]);
+    let mut freq: Vec<(char, u64)> = counts.into_iter().collect();
+    sort_freq_by_order(&mut freq, SortKey::CountDesc, &order);
+    assert_eq!(freq, [('z', 2), ('y', 2), ('x', 1)]);
+    sort_freq_by_order(&mut freq, SortKey::CountAsc, &order);
+    assert_eq!(freq, [('x', 1), ('z', 2), ('y', 2)]);
+}
+
+#[test]
+fn listings_with_equal_counts_are_the_same_every_time() {
+    // a to d twice each, in the reverse of code point order, and e once
+    let counts = count_chars(&["dcba", "e", "dcba"]);
+    let list = |key, order, top_percent, n| list_freq(&counts, key, order, top_percent, n);
+    assert_eq!(list(SortKey::CountDesc, None, None, usize::MAX), [('a', 2), ('b', 2), ('c', 2), ('d', 2), ('e', 1)]);
+    assert_eq!(list(SortKey::CountDesc, None, None, 3), [('a', 2), ('b', 2), ('c', 2)]);
+    assert_eq!(list(SortKey::CountAsc, None, None, 3), [('e', 1), ('a', 2), ('b', 2)]);
+    assert_eq!(list(SortKey::CharDesc, None, None, 2), [('e', 1), ('d', 2)]);
+    // 6 of the 9 occurrences are the first 3 by count, ties and all
+    assert_eq!(list(SortKey::CountDesc, None, Some(50.0), usize::MAX), [('a', 2), ('b', 2), ('c', 2)]);
+    assert_eq!(list(SortKey::CharDesc, None, Some(50.0), usize::MAX), [('c', 2), ('b', 2), ('a', 2)]);
+    let order = ['d', 'c', 'b', 'a', 'e'];
+    assert_eq!(list(SortKey::CountDesc, Some(&order), None, 3), [('d', 2), ('c', 2), ('b', 2)]);
+    assert_eq!(list(SortKey::CountDesc, None, None, 0), []);
+}
+
+#[cfg(feature = "collation")]
+fn alphabetical(text: &str) -> String {
+    let mut freq: Vec<(char, u64)> = text.chars().map(|c| (c, 1)).collect();
+    sort_freq(&mut freq, SortKey::CollatedAsc);
+    freq.into_iter().map(|(c, _)| c).collect()
+}
+
+#[cfg(feature = "collation")]
+#[test]
+fn accented_letters_follow_their_base_letter() {
+    assert_eq!(alphabetical("fêtezçàéaèc"), "aàcçeèéêftz");
+}
+
+#[cfg(feature = "collation")]
+#[test]
+fn case_pairs_stay_together() {
+    assert_eq!(alphabetical("bBaAÉeE"), "AaBbEeÉ");
+}
+
+#[test]
+fn char_order_is_the_same_with_and_without_collation() {
+    let mut freq: Vec<(char, u64)> = "fêtezçàéaècBA".chars().map(|c| (c, 1)).collect();
+    sort_freq(&mut freq, SortKey::CharAsc);
+    assert_eq!(freq.iter().map(|(c, _)| c).collect::<String>(), "ABaceftzàçèéê");
+}